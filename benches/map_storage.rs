@@ -0,0 +1,104 @@
+//! Compare sparse (HashMap), dense (array) and chunked storage of `Map`.
+//!
+//! Run with `cargo bench --bench map_storage`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use aoc2020::map::{Bounds, Map};
+
+const SIZE: usize = 256;
+const ROUNDS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Storage {
+    Sparse,
+    Dense,
+    Chunked,
+}
+
+fn build_map(storage: Storage) -> Map<[usize; 2], u8> {
+    let mut map = match storage {
+        Storage::Sparse => Map::new(),
+        Storage::Dense => Map::new_dense(Bounds::new([0, 0], [SIZE - 1, SIZE - 1])),
+        Storage::Chunked => Map::new_chunked(16),
+    };
+
+    for i in 0..SIZE {
+        for j in 0..SIZE {
+            map.set([i, j], ((i * 7 + j * 13) % 5) as u8);
+        }
+    }
+
+    map
+}
+
+fn get_all(map: &Map<[usize; 2], u8>) -> usize {
+    let mut sum = 0;
+    for i in 0..SIZE {
+        for j in 0..SIZE {
+            sum += *map.get(&[i, j]).unwrap() as usize;
+        }
+    }
+    sum
+}
+
+const OFFSETS: [(isize, isize); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+
+fn count_neighbors(map: &Map<[usize; 2], u8>) -> usize {
+    let mut n = 0;
+    for ([i, j], _) in map.iter() {
+        for (di, dj) in OFFSETS.iter() {
+            let (ni, nj) = (i as isize + di, j as isize + dj);
+            if ni < 0 || nj < 0 {
+                continue;
+            }
+
+            if let Some(0) = map.get(&[ni as usize, nj as usize]) {
+                n += 1;
+            }
+        }
+    }
+    n
+}
+
+fn bench<F: FnMut() -> usize>(name: &str, mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        black_box(f());
+    }
+    let elapsed = start.elapsed() / ROUNDS as u32;
+    println!("{:32} {:>12.3?}", name, elapsed);
+    elapsed
+}
+
+fn main() {
+    println!("{}x{} map, mean over {} rounds\n", SIZE, SIZE, ROUNDS);
+
+    for &storage in &[Storage::Sparse, Storage::Dense, Storage::Chunked] {
+        let label = format!("{:?}", storage).to_lowercase();
+        let map = build_map(storage);
+        assert_eq!(map.is_dense(), storage == Storage::Dense);
+        assert_eq!(map.is_chunked(), storage == Storage::Chunked);
+
+        bench(&format!("{} build", label), || build_map(storage).len());
+        bench(&format!("{} get", label), || get_all(&map));
+        bench(&format!("{} iterate", label), || {
+            map.values().map(|v| *v as usize).sum()
+        });
+        bench(&format!("{} neighbor count", label), || {
+            count_neighbors(&map)
+        });
+        bench(&format!("{} clone", label), || map.clone().len());
+        println!();
+    }
+}
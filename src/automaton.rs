@@ -0,0 +1,386 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::map::{Map, MapCoordinate, MapDiff, Neighborhood};
+use crate::parallel::par_map;
+
+/// Function that lists the coordinates a cell looks at when applying a rule
+pub type NeighborFn<C, T> = Box<dyn Fn(&Map<C, T>, &C) -> Vec<C> + Send + Sync>;
+
+/// How a simulation run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The map at `generation` is identical to the one after it
+    FixedPoint { generation: usize },
+
+    /// The map at the current generation was already seen at generation `start`
+    Cycle { start: usize, period: usize },
+
+    /// The maximum number of generations was reached
+    Limit { generation: usize },
+}
+
+/// A cellular automaton that repeatedly applies a rule to every cell of a map.
+///
+/// The rule gets the coordinate of a cell, its current tile and the defined tiles of its
+/// neighbors, and returns the next tile for the cell. Returning `None` clears the cell.
+/// All cells are updated simultaneously.
+pub struct Automaton<C: MapCoordinate, T, R> {
+    pub map: Map<C, T>,
+    pub generation: usize,
+    neighbors: NeighborFn<C, T>,
+    rule: R,
+    grow: bool,
+    incremental: bool,
+    seen: HashMap<u64, usize>,
+    last_diff: MapDiff<C>,
+}
+
+impl<C, T, R> Automaton<C, T, R>
+where
+    C: MapCoordinate + 'static,
+    T: Clone + PartialEq + std::hash::Hash,
+    R: Fn(&C, Option<&T>, &[&T]) -> Option<T>,
+{
+    /// Create a new automaton where cells look at the coordinates in a neighborhood
+    pub fn new(map: Map<C, T>, neighborhood: Neighborhood, rule: R) -> Self {
+        let offsets = C::neighbor_offsets(neighborhood);
+        Automaton {
+            map,
+            generation: 0,
+            neighbors: Box::new(move |map, coord| {
+                offsets
+                    .iter()
+                    .filter_map(|ofs| map.offset(coord, ofs))
+                    .collect()
+            }),
+            rule,
+            grow: false,
+            incremental: false,
+            seen: HashMap::new(),
+            last_diff: MapDiff::default(),
+        }
+    }
+
+    /// Use a custom function to determine which coordinates a cell looks at
+    pub fn with_neighbors<F>(mut self, neighbors: F) -> Self
+    where
+        F: Fn(&Map<C, T>, &C) -> Vec<C> + Send + Sync + 'static,
+    {
+        self.neighbors = Box::new(neighbors);
+        self
+    }
+
+    /// Also evaluate undefined cells next to defined ones, so that the map can grow
+    /// indefinitely. Otherwise, only defined cells are updated.
+    ///
+    /// Growth assumes that the neighbor relation is symmetric.
+    pub fn growing(mut self) -> Self {
+        self.grow = true;
+        self
+    }
+
+    /// Only re-evaluate cells next to cells that changed since the previous generation,
+    /// as recorded by the journal of the map. The first generation evaluates all cells.
+    ///
+    /// This assumes that the neighbor relation is symmetric.
+    pub fn incremental(mut self) -> Self {
+        self.incremental = true;
+        self.map.disable_journal();
+        self
+    }
+
+    /// Get the changes made by the last generation
+    pub fn last_diff(&self) -> &MapDiff<C> {
+        &self.last_diff
+    }
+
+    /// Get all coordinates that the rule should be evaluated on
+    fn active_cells(&mut self) -> Vec<C> {
+        let touched = if self.incremental {
+            let touched = self.map.take_journal();
+            self.map.enable_journal();
+            touched
+        } else {
+            None
+        };
+
+        if let Some(touched) = touched {
+            let mut active: HashSet<C> = HashSet::new();
+            for coord in touched {
+                active.insert(coord);
+                active.extend((self.neighbors)(&self.map, &coord));
+            }
+
+            let map = &self.map;
+            let neighbors = &self.neighbors;
+            active.retain(|c| {
+                map.get(c).is_some()
+                    || (self.grow && neighbors(map, c).iter().any(|n| map.get(n).is_some()))
+            });
+            return active.into_iter().collect();
+        }
+
+        if !self.grow {
+            return self.map.keys().collect();
+        }
+
+        let mut active: HashSet<C> = HashSet::new();
+        for coord in self.map.keys() {
+            active.insert(coord);
+            active.extend((self.neighbors)(&self.map, &coord));
+        }
+        active.into_iter().collect()
+    }
+
+    /// Apply the rule to a cell, getting its next tile if it changes
+    fn evaluate(&self, coord: &C) -> Option<(C, Option<T>)> {
+        let current = self.map.get(coord);
+        let neighbors: Vec<&T> = (self.neighbors)(&self.map, coord)
+            .iter()
+            .filter_map(|n| self.map.get(n))
+            .collect();
+
+        let next = (self.rule)(coord, current, &neighbors);
+        if next.as_ref() != current {
+            Some((*coord, next))
+        } else {
+            None
+        }
+    }
+
+    /// Advance the automaton by one generation, returning the number of changed cells
+    pub fn step(&mut self) -> usize {
+        let changes: Vec<(C, Option<T>)> = self
+            .active_cells()
+            .iter()
+            .filter_map(|coord| self.evaluate(coord))
+            .collect();
+
+        self.apply(changes)
+    }
+
+    /// Write the changes of a generation to the map
+    fn apply(&mut self, changes: Vec<(C, Option<T>)>) -> usize {
+        let mut diff = MapDiff::default();
+        for (coord, tile) in changes {
+            match (tile, self.map.get(&coord).is_some()) {
+                (Some(tile), defined) => {
+                    if defined {
+                        diff.changed.push(coord);
+                    } else {
+                        diff.added.push(coord);
+                    }
+                    self.map.set(coord, tile);
+                }
+                (None, _) => {
+                    diff.removed.push(coord);
+                    self.map.remove(&coord);
+                }
+            }
+        }
+
+        self.generation += 1;
+        self.last_diff = diff;
+        self.last_diff.len()
+    }
+
+    /// Advance the automaton by one generation like `step`, evaluating cells on all
+    /// available threads
+    pub fn par_step(&mut self) -> usize
+    where
+        C: Send + Sync,
+        T: Send + Sync,
+        R: Sync,
+    {
+        let active = self.active_cells();
+        let changes: Vec<(C, Option<T>)> = par_map(&active, |coord| self.evaluate(coord))
+            .into_iter()
+            .flatten()
+            .collect();
+
+        self.apply(changes)
+    }
+
+    /// Step the automaton until it reaches a fixed point, enters a cycle or has run
+    /// for `max_generations` in total
+    pub fn run(&mut self, max_generations: Option<usize>) -> Outcome {
+        self.seen.insert(self.map.state_hash(), self.generation);
+
+        loop {
+            if let Some(max) = max_generations {
+                if self.generation >= max {
+                    return Outcome::Limit {
+                        generation: self.generation,
+                    };
+                }
+            }
+
+            if self.step() == 0 {
+                return Outcome::FixedPoint {
+                    generation: self.generation - 1,
+                };
+            }
+
+            if let Some(start) = self.seen.insert(self.map.state_hash(), self.generation) {
+                return Outcome::Cycle {
+                    start,
+                    period: self.generation - start,
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{Bounds, Topology};
+
+    fn life(_: &[i64; 2], tile: Option<&bool>, neighbors: &[&bool]) -> Option<bool> {
+        match (tile.is_some(), neighbors.len()) {
+            (true, 2) | (_, 3) => Some(true),
+            _ => None,
+        }
+    }
+
+    fn from_cells(cells: &[[i64; 2]]) -> Map<[i64; 2], bool> {
+        let mut map = Map::new();
+        for c in cells {
+            map.set(*c, true);
+        }
+        map
+    }
+
+    #[test]
+    fn test_fixed_point() {
+        let block = from_cells(&[[0, 0], [0, 1], [1, 0], [1, 1]]);
+        let mut automaton = Automaton::new(block.clone(), Neighborhood::Moore(1), life).growing();
+
+        assert_eq!(automaton.run(None), Outcome::FixedPoint { generation: 0 });
+        assert_eq!(automaton.map, block);
+    }
+
+    #[test]
+    fn test_cycle() {
+        let blinker = from_cells(&[[0, -1], [0, 0], [0, 1]]);
+        let mut automaton = Automaton::new(blinker.clone(), Neighborhood::Moore(1), life).growing();
+
+        assert_eq!(automaton.step(), 4);
+        assert_eq!(automaton.map, from_cells(&[[-1, 0], [0, 0], [1, 0]]));
+
+        assert_eq!(
+            automaton.run(None),
+            Outcome::Cycle {
+                start: 1,
+                period: 2
+            }
+        );
+        assert_eq!(automaton.generation, 3);
+    }
+
+    #[test]
+    fn test_growth() {
+        let glider = from_cells(&[[0, 1], [1, 2], [2, 0], [2, 1], [2, 2]]);
+
+        let mut bounded = Automaton::new(glider.clone(), Neighborhood::Moore(1), life);
+        assert_eq!(bounded.run(Some(4)), Outcome::FixedPoint { generation: 1 });
+        assert_eq!(bounded.map, from_cells(&[[1, 2], [2, 1], [2, 2]]));
+
+        let mut growing = Automaton::new(glider, Neighborhood::Moore(1), life).growing();
+        assert_eq!(growing.run(Some(4)), Outcome::Limit { generation: 4 });
+        assert_eq!(
+            growing.map,
+            from_cells(&[[1, 2], [2, 3], [3, 1], [3, 2], [3, 3]])
+        );
+    }
+
+    #[test]
+    fn test_torus() {
+        let glider = from_cells(&[[0, 1], [1, 2], [2, 0], [2, 1], [2, 2]]);
+        let mut map = glider.clone();
+        map.fixed_extent = Some(Bounds::new([0, 0], [5, 5]));
+        map.set_topology(&[Topology::Wrap, Topology::Wrap]);
+
+        // the glider moves by one cell diagonally every 4 generations
+        let mut automaton = Automaton::new(map, Neighborhood::Moore(1), life).growing();
+        assert_eq!(
+            automaton.run(Some(24)),
+            Outcome::Cycle {
+                start: 0,
+                period: 24
+            }
+        );
+        assert!(automaton.map.keys().all(|[i, j]| i < 6 && j < 6));
+        assert!(glider.iter().all(|(c, t)| automaton.map.get(&c) == Some(t)));
+    }
+
+    #[test]
+    fn test_custom_neighbors() {
+        // rule 90: a cell is alive if exactly one of its left and right neighbors is
+        let map = from_cells(&[[0, 0]]);
+        let mut automaton = Automaton::new(map, Neighborhood::Moore(1), |_, _, n| {
+            if n.len() == 1 {
+                Some(true)
+            } else {
+                None
+            }
+        })
+        .with_neighbors(|_, [i, j]| vec![[*i, j - 1], [*i, j + 1]])
+        .growing();
+
+        assert_eq!(automaton.run(Some(3)), Outcome::Limit { generation: 3 });
+        assert_eq!(
+            automaton.map,
+            from_cells(&[[0, -3], [0, -1], [0, 1], [0, 3]])
+        );
+    }
+
+    #[test]
+    fn test_incremental() {
+        let cells = [
+            [0, 1],
+            [1, 2],
+            [2, 0],
+            [2, 1],
+            [2, 2],
+            [6, 6],
+            [6, 7],
+            [6, 8],
+        ];
+        let mut full = Automaton::new(from_cells(&cells), Neighborhood::Moore(1), life).growing();
+        let mut incremental = Automaton::new(from_cells(&cells), Neighborhood::Moore(1), life)
+            .growing()
+            .incremental();
+
+        for _ in 0..12 {
+            assert_eq!(incremental.step(), full.step());
+            assert_eq!(incremental.map, full.map);
+        }
+
+        let mut full = Automaton::new(from_cells(&cells), Neighborhood::Moore(1), life);
+        let mut incremental =
+            Automaton::new(from_cells(&cells), Neighborhood::Moore(1), life).incremental();
+        assert_eq!(
+            incremental.run(Some(10)),
+            Outcome::FixedPoint { generation: 2 }
+        );
+        assert_eq!(full.run(Some(10)), Outcome::FixedPoint { generation: 2 });
+        assert_eq!(incremental.map, full.map);
+    }
+
+    #[test]
+    fn test_last_diff() {
+        let blinker = from_cells(&[[0, -1], [0, 0], [0, 1]]);
+        let mut automaton = Automaton::new(blinker, Neighborhood::Moore(1), life).growing();
+        automaton.step();
+
+        let diff = automaton.last_diff();
+        let mut added = diff.added.clone();
+        let mut removed = diff.removed.clone();
+        added.sort_unstable();
+        removed.sort_unstable();
+        assert_eq!(added, vec![[-1, 0], [1, 0]]);
+        assert_eq!(removed, vec![[0, -1], [0, 1]]);
+        assert!(diff.changed.is_empty());
+    }
+}
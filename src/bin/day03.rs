@@ -2,7 +2,7 @@ use std::fs::File;
 
 use snafu::{ResultExt, Snafu};
 
-use aoc2020::map::{Map, MapError, ParseMapTile};
+use aoc2020::map::{Map, MapError, MapReader, ParseMapTile, Topology};
 
 #[derive(Debug, Snafu)]
 enum Error {
@@ -48,17 +48,15 @@ impl std::fmt::Display for Tile {
     }
 }
 
-fn count_trees(map: &mut Map<[usize; 2], Tile>, di: usize, dj: usize) -> usize {
-    let (_, [imax, jmax]) = map.get_extent();
-    let mut i = 0;
-    let mut j = 0;
+fn count_trees(map: &mut Map<[usize; 2], Tile>, di: i64, dj: i64) -> usize {
+    let mut pos = Some([0, 0]);
     let mut hit_trees = 0;
 
-    while i <= imax {
-        let current = { map.get(&[i, j]).cloned() };
+    while let Some(p) = pos {
+        let current = { map.get(&p).cloned() };
 
         map.set(
-            [i, j],
+            p,
             match current {
                 None => Tile::PathEmpty,
                 Some(Tile::Tree) => {
@@ -69,8 +67,7 @@ fn count_trees(map: &mut Map<[usize; 2], Tile>, di: usize, dj: usize) -> usize {
             },
         );
 
-        i += di;
-        j = (j + dj) % (jmax + 1);
+        pos = map.offset(&p, &[di, dj]);
     }
 
     hit_trees
@@ -82,7 +79,12 @@ fn main() -> Result<()> {
         filename: filename.to_string(),
     })?;
 
-    let map = Map::<[usize; 2], Tile>::read(&mut f).context(MapLoading)?;
+    let mut map: Map<[usize; 2], Tile> = MapReader::new()
+        .void_chars(".")
+        .read(&mut f)
+        .context(MapLoading)?;
+    map.set_topology(&[Topology::Bounded, Topology::Wrap]);
+
     let recipes = vec![(1, 1), (1, 3), (1, 5), (1, 7), (2, 1)];
     let mut product = 1;
     for (di, dj) in recipes {
@@ -1,9 +1,11 @@
-use std::collections::HashMap;
 use std::fs::File;
+use std::io::IsTerminal;
 
 use snafu::{ResultExt, Snafu};
 
-use aoc2020::map::{Map, MapError, ParseMapTile};
+use aoc2020::automaton::Automaton;
+use aoc2020::image::{FrameWriter, ImageError, TileColor};
+use aoc2020::map::{Map, MapCoordinate, MapError, Neighborhood, ParseMapTile};
 
 #[derive(Debug, Snafu)]
 enum Error {
@@ -15,11 +17,14 @@ enum Error {
 
     #[snafu(display("Map error: {}", source))]
     MapLoading { source: MapError },
+
+    #[snafu(display("Image error: {}", source))]
+    Image { source: ImageError },
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 enum Tile {
     Floor,
     Chair { occupied: bool },
@@ -50,80 +55,84 @@ impl std::fmt::Display for Tile {
     }
 }
 
-fn step(map: &mut Map<[usize; 2], Tile>, cast_ray: bool, max_neighbors: usize) {
-    // count number of per-chair neighbors
-    let mut neighbors: HashMap<[usize; 2], usize> = HashMap::new();
-    for ([i, j], tile) in map.data.iter() {
-        if let Tile::Chair { .. } = tile {
-            let mut n = 0;
-            for idir in -1..=1 {
-                for jdir in -1..=1 {
-                    if idir == 0 && jdir == 0 {
-                        continue;
-                    }
-
-                    let mut k = 1;
-                    loop {
-                        let ic = (*i as i64) + k * idir;
-                        let jc = (*j as i64) + k * jdir;
-                        if ic < 0 || jc < 0 {
-                            break;
-                        }
-
-                        match map.get(&[ic as usize, jc as usize]) {
-                            Some(Tile::Chair { occupied }) => {
-                                if *occupied {
-                                    n += 1;
-                                }
-                                break;
-                            }
-                            None => break,
-                            _ => {}
-                        }
-
-                        if !cast_ray {
-                            break;
-                        }
-                        k += 1;
-                    }
-                }
-            }
-
-            neighbors.insert([*i, *j], n);
+impl TileColor for Tile {
+    fn color(&self) -> [u8; 3] {
+        match self {
+            Tile::Floor => [40, 40, 40],
+            Tile::Chair { occupied: false } => [90, 160, 90],
+            Tile::Chair { occupied: true } => [220, 120, 40],
         }
     }
+}
 
-    // apply update rule
-    for (coord, tile) in map.data.iter_mut() {
-        if let Tile::Chair { ref mut occupied } = tile {
-            let filled_neighbors = neighbors[coord];
-            if !*occupied && filled_neighbors == 0 {
-                *occupied = true;
-            } else if *occupied && filled_neighbors >= max_neighbors {
-                *occupied = false;
-            }
-        }
-    }
+/// Get the first chair that is visible in a straight line in each direction
+fn visible_chairs(map: &Map<[usize; 2], Tile>, pos: &[usize; 2]) -> Vec<[usize; 2]> {
+    let directions = <[usize; 2]>::neighbor_offsets(Neighborhood::Moore(1));
+    map.visible_from(pos, &directions, |_, t| matches!(t, Tile::Chair { .. }))
+        .into_iter()
+        .map(|(c, _)| c)
+        .collect()
 }
 
 fn loop_until_stabilized(
-    mut map: Map<[usize; 2], Tile>,
+    map: Map<[usize; 2], Tile>,
     cast_ray: bool,
     max_neighbors: usize,
-) -> Map<[usize; 2], Tile> {
+    mut frames: Option<FrameWriter>,
+) -> Result<Map<[usize; 2], Tile>> {
+    let rule = move |_: &[usize; 2], tile: Option<&Tile>, neighbors: &[&Tile]| match tile {
+        Some(Tile::Chair { occupied }) => {
+            let filled_neighbors = neighbors
+                .iter()
+                .filter(|t| **t == &Tile::Chair { occupied: true })
+                .count();
+
+            Some(Tile::Chair {
+                occupied: if !*occupied && filled_neighbors == 0 {
+                    true
+                } else if *occupied && filled_neighbors >= max_neighbors {
+                    false
+                } else {
+                    *occupied
+                },
+            })
+        }
+        t => t.cloned(),
+    };
+
+    // Seats see each other symmetrically, so only cells next to changes need updating
+    let mut automaton = Automaton::new(map, Neighborhood::Moore(1), rule).incremental();
+    if cast_ray {
+        automaton = automaton.with_neighbors(visible_chairs);
+    }
+
+    // Printing every generation is slow, so it is opt-in. Diffs are only colored on
+    // terminals.
+    let show_steps = std::env::var_os("AOC_STEPS").is_some();
+    let color = std::io::stdout().is_terminal();
+
     loop {
-        let map_last = map.clone();
-        step(&mut map, cast_ray, max_neighbors);
-        println!("{}", map);
-        if map == map_last {
-            return map;
+        if let Some(frames) = &mut frames {
+            frames.write_frame(&automaton.map).context(Image)?;
+        }
+
+        if automaton.par_step() == 0 {
+            break;
+        }
+        if show_steps && color {
+            println!("{}", automaton.map.display_diff(automaton.last_diff()));
+        } else if show_steps {
+            println!("{}", automaton.map);
         }
     }
+    println!("{}", automaton.map);
+
+    Ok(automaton.map)
 }
 
 fn count_filled_seats(map: &Map<[usize; 2], Tile>) -> usize {
     let mut filled_seats = 0;
-    for tile in map.data.values() {
+    for tile in map.values() {
         if let Tile::Chair { occupied: true } = tile {
             filled_seats += 1;
         }
@@ -139,10 +148,20 @@ fn main() -> Result<()> {
 
     let map_original = Map::<[usize; 2], Tile>::read(&mut f).context(MapLoading)?;
 
-    let map1 = loop_until_stabilized(map_original.clone(), false, 4);
+    let map1 = loop_until_stabilized(
+        map_original.clone(),
+        false,
+        4,
+        FrameWriter::from_env("day11_part1_"),
+    )?;
     println!("Part 1: Got {} filled seats", count_filled_seats(&map1));
 
-    let map2 = loop_until_stabilized(map_original.clone(), true, 5);
+    let map2 = loop_until_stabilized(
+        map_original.clone(),
+        true,
+        5,
+        FrameWriter::from_env("day11_part2_"),
+    )?;
     println!("Part 2: Got {} filled seats", count_filled_seats(&map2));
 
     Ok(())
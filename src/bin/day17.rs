@@ -1,8 +1,8 @@
-use std::collections::{HashMap, HashSet};
-
 use snafu::{ResultExt, Snafu};
 
-use aoc2020::map::{Map, MapCoordinate, MapError, MapTile, ParseMapTile};
+use aoc2020::automaton::Automaton;
+use aoc2020::image::{FrameWriter, ImageError, TileColor};
+use aoc2020::map::{Map, MapCoordinate, MapError, Neighborhood, ParseMapTile};
 
 #[derive(Debug, Snafu)]
 enum Error {
@@ -12,13 +12,16 @@ enum Error {
     #[snafu(display("Map parsing error: {}", source))]
     ParseMap { source: MapError },
 
+    #[snafu(display("Image error: {}", source))]
+    Image { source: ImageError },
+
     #[snafu(display("Field parsing error"))]
     ParseField,
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 enum Tile {
     Inactive,
     Active,
@@ -37,6 +40,15 @@ impl std::fmt::Display for Tile {
     }
 }
 
+impl TileColor for Tile {
+    fn color(&self) -> [u8; 3] {
+        match self {
+            Tile::Inactive => [0, 0, 0],
+            Tile::Active => [255, 255, 255],
+        }
+    }
+}
+
 impl ParseMapTile for Tile {
     fn from_char(c: char) -> Option<Self> {
         Some(match c {
@@ -47,98 +59,9 @@ impl ParseMapTile for Tile {
     }
 }
 
-trait NeighborsIter
-where
-    Self: MapCoordinate,
-{
-    fn get_neighbor_positions(&self) -> Vec<Self>;
-    fn get_in_range_positions<T: MapTile>(map: &Map<Self, T>) -> Vec<Self>;
-}
-
-impl NeighborsIter for [i64; 3] {
-    fn get_neighbor_positions(&self) -> Vec<Self> {
-        let mut out = Vec::new();
-        for iofs in -1..=1 {
-            for jofs in -1..=1 {
-                for kofs in -1..=1 {
-                    if iofs == 0 && jofs == 0 && kofs == 0 {
-                        continue;
-                    }
-                    out.push([self[0] + iofs, self[1] + jofs, self[2] + kofs]);
-                }
-            }
-        }
-        out
-    }
-
-    fn get_in_range_positions<T: MapTile>(map: &Map<Self, T>) -> Vec<Self> {
-        let (min, max) = map.get_extent();
-
-        let mut out = Vec::new();
-        for i in min[0] - 1..=max[0] + 1 {
-            for j in min[1] - 1..=max[1] + 1 {
-                for k in min[2] - 1..=max[2] + 1 {
-                    out.push([i, j, k]);
-                }
-            }
-        }
-        out
-    }
-}
-
-impl NeighborsIter for [i64; 4] {
-    fn get_neighbor_positions(&self) -> Vec<Self> {
-        let mut out = Vec::new();
-        for iofs in -1..=1 {
-            for jofs in -1..=1 {
-                for kofs in -1..=1 {
-                    for lofs in -1..=1 {
-                        if iofs == 0 && jofs == 0 && kofs == 0 && lofs == 0 {
-                            continue;
-                        }
-                        out.push([
-                            self[0] + iofs,
-                            self[1] + jofs,
-                            self[2] + kofs,
-                            self[3] + lofs,
-                        ]);
-                    }
-                }
-            }
-        }
-        out
-    }
-
-    fn get_in_range_positions<T: MapTile>(map: &Map<Self, T>) -> Vec<Self> {
-        let (min, max) = map.get_extent();
-
-        let mut out = Vec::new();
-        for i in min[0] - 1..=max[0] + 1 {
-            for j in min[1] - 1..=max[1] + 1 {
-                for k in min[2] - 1..=max[2] + 1 {
-                    for l in min[3] - 1..=max[3] + 1 {
-                        out.push([i, j, k, l]);
-                    }
-                }
-            }
-        }
-        out
-    }
-}
-
-fn count_neighbors<P: NeighborsIter>(map: &Map<P, Tile>, pos: P) -> usize {
-    let mut n_active = 0;
-    for n in pos.get_neighbor_positions() {
-        if let Some(Tile::Active) = map.get(&n) {
-            n_active += 1;
-        }
-    }
-    n_active
-}
-
 fn count_active<C: MapCoordinate>(map: &Map<C, Tile>) -> usize {
     let mut n_active = 0;
-    for tile in map.data.values() {
+    for tile in map.values() {
         if tile == &Tile::Active {
             n_active += 1;
         }
@@ -146,23 +69,13 @@ fn count_active<C: MapCoordinate>(map: &Map<C, Tile>) -> usize {
     n_active
 }
 
-fn step<P: NeighborsIter>(map: &mut Map<P, Tile>) {
-    let neighbors: HashMap<P, usize> = P::get_in_range_positions::<Tile>(&map)
-        .into_iter()
-        .map(|pos| (pos, count_neighbors(&map, pos)))
-        .collect();
-
-    for (pos, n) in neighbors {
-        let current = map.get(&pos).unwrap_or(&Tile::Inactive).clone();
-        map.set(
-            pos,
-            match (n, current) {
-                (n, Tile::Active) if n >= 2 && n <= 3 => Tile::Active,
-                (3, Tile::Inactive) => Tile::Active,
-                _ => Tile::Inactive,
-            },
-        );
-    }
+fn conway_rule<C>(_: &C, tile: Option<&Tile>, neighbors: &[&Tile]) -> Option<Tile> {
+    let n = neighbors.iter().filter(|t| **t == &Tile::Active).count();
+    Some(match (n, tile) {
+        (n, Some(Tile::Active)) if n >= 2 && n <= 3 => Tile::Active,
+        (3, _) => Tile::Active,
+        _ => Tile::Inactive,
+    })
 }
 
 fn main() -> Result<()> {
@@ -171,25 +84,42 @@ fn main() -> Result<()> {
         .parse()
         .context(ParseMap)?;
 
-    let mut map3d: Map<[i64; 3], Tile> = Map::from_2d(&map2d);
-    let mut map4d: Map<[i64; 4], Tile> = Map::from_3d(&map3d);
+    // The pocket dimension grows by at most one cell per cycle in every direction
+    let frame_extent = map2d.bounds().expand(6);
+    let mut frames = FrameWriter::from_env("day17_z0_");
+
+    // The pocket dimensions grow every cycle, so store them in chunks
+    let mut map3d: Map<[i64; 3], Tile> = map2d.embed();
+    let mut map4d: Map<[i64; 4], Tile> = map2d.embed();
+    map3d.to_chunked(8);
+    map4d.to_chunked(4);
+
+    let mut automaton3d = Automaton::new(map3d, Neighborhood::Moore(1), conway_rule).growing();
+    let mut automaton4d = Automaton::new(map4d, Neighborhood::Moore(1), conway_rule).growing();
 
     for cycle in 1..=6 {
         println!("\nCYCLE {} \\\\\\\\\\\\\\\\\\", cycle);
-        step(&mut map3d);
-        step(&mut map4d);
+        automaton3d.par_step();
+        automaton4d.par_step();
+        let (map3d, map4d) = (&automaton3d.map, &automaton4d.map);
+
+        if let Some(frames) = &mut frames {
+            let mut layer: Map<[i64; 2], Tile> = map3d.slice(0, 0);
+            layer.fixed_extent = Some(frame_extent);
+            frames.write_frame(&layer).context(Image)?;
+        }
 
         // println!("{}", map3d);
         // println!("{}", map4d);
 
         println!(
             "Part 1: Got {} active tiles, extent {:?}",
-            count_active(&map3d),
+            count_active(map3d),
             map3d.get_extent()
         );
         println!(
             "Part 2: Got {} active tiles, extent {:?}",
-            count_active(&map4d),
+            count_active(map4d),
             map4d.get_extent()
         );
     }
@@ -1,7 +1,7 @@
 use std::{
     collections::{HashMap, HashSet},
     fs::File,
-    io::{BufRead, BufReader, Read},
+    io::{BufRead, BufReader, IsTerminal, Read},
 };
 
 use lazy_static::lazy_static;
@@ -9,7 +9,9 @@ use regex::Regex;
 
 use snafu::{ResultExt, Snafu};
 
-use aoc2020::map::{Map, MapError, MapTile, ParseMapTile};
+use aoc2020::map::{Bounds, Map, MapError, MapReader, ParseMapTile};
+use aoc2020::render::{Color, Overlay, Renderer};
+use aoc2020::transform::Transform;
 
 lazy_static! {
     static ref RE_TILE: Regex = Regex::new(r"Tile (\d+)").unwrap();
@@ -123,96 +125,19 @@ impl From<Vec<Tile>> for Border {
     }
 }
 
-/// Transformation over vertical flips and right rotations.
-/// flips are always applied before rotations.
-/// Note that this is a group together with horizontal/vertical flips and right/left rotations.
-#[derive(Clone, PartialEq, Eq, Hash, Copy)]
-struct Transform {
-    flip: bool,
-    rotate: u8,
-}
-
-impl Transform {
-    fn new() -> Self {
-        Transform {
-            flip: false,
-            rotate: 0,
-        }
-    }
-    fn apply_to<T: MapTile>(&self, map: &Map<[usize; 2], T>) -> Map<[usize; 2], T> {
-        let mut out = map.clone();
-
-        if self.flip {
-            out = out.flip(0);
-        }
-
-        for _ in 0..self.rotate {
-            out = out.rotate_right()
-        }
-
-        out
-    }
-
-    fn get_side(&self, side: u8) -> u8 {
-        (if self.flip {
-            6 - side - self.rotate
-        } else {
-            self.rotate + side
-        }) % 4
-    }
-}
-
-impl std::ops::Add for Transform {
-    type Output = Transform;
-    fn add(self, rhs: Self) -> Self::Output {
-        let (flip, rotate) = match (self.flip, rhs.flip) {
-            (false, false) => (false, self.rotate + rhs.rotate),
-            (false, true) => (true, (8 - self.rotate) + rhs.rotate),
-            (true, false) => (true, self.rotate + rhs.rotate),
-            (true, true) => (false, (8 - self.rotate) + rhs.rotate),
-        };
-
-        Transform {
-            flip,
-            rotate: rotate % 4,
-        }
-    }
-}
-
-impl std::ops::Neg for Transform {
-    type Output = Transform;
-
-    fn neg(self) -> Self::Output {
-        Transform {
-            flip: self.flip,
-            rotate: if self.flip {
-                self.rotate
-            } else {
-                (4 - self.rotate) % 4
-            },
-        }
-    }
-}
-
-impl std::ops::Sub for Transform {
-    type Output = Transform;
-
-    fn sub(self, rhs: Self) -> Self::Output {
-        self + (-rhs)
-    }
-}
-
-impl std::fmt::Debug for Transform {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "<{}{}>", if self.flip { "F" } else { " " }, self.rotate)
-    }
+/// Get the side of the original tile that ends up at `side` after transforming it.
+/// Sides are numbered clockwise starting from the top.
+fn get_side(transform: &Transform, side: u8) -> u8 {
+    (if transform.is_flipped() {
+        6 - side - transform.rotations()
+    } else {
+        transform.rotations() + side
+    }) % 4
 }
 
 fn get_top_border(map: &Map<[usize; 2], Tile>) -> Border {
     let (min, max) = map.get_extent();
-    let tiles: Vec<Tile> = (min[1]..=max[1])
-        .map(|j| map.data[&[0, j]].clone())
-        .collect();
+    let tiles: Vec<Tile> = (min[1]..=max[1]).map(|j| map[&[0, j]].clone()).collect();
 
     Border { tiles }
 }
@@ -220,15 +145,9 @@ fn get_top_border(map: &Map<[usize; 2], Tile>) -> Border {
 fn border_codes(map: &Map<[usize; 2], Tile>) -> HashSet<(Transform, Border)> {
     let mut out = HashSet::new();
 
-    for flip in &[false, true] {
-        for rotate in 0..4 {
-            let transform = Transform {
-                flip: *flip,
-                rotate,
-            };
-            let border = get_top_border(&transform.apply_to(map));
-            out.insert((transform, border));
-        }
+    for (transform, transformed) in map.all_orientations() {
+        let border = get_top_border(&transformed);
+        out.insert((transform, border));
     }
 
     out
@@ -242,7 +161,7 @@ fn solve(
     let mut out = HashMap::new();
 
     let mut current = top_left_corner;
-    let mut current_transform = Transform::new();
+    let mut current_transform = Transform::identity();
     let mut i = 0;
     let mut j = 0;
 
@@ -250,19 +169,19 @@ fn solve(
         // println!("{} {}: {} {:?}", i, j, current, current_transform);
         out.insert((i, j), (current, current_transform));
 
-        let right_side = current_transform.get_side(3);
+        let right_side = get_side(&current_transform, 3);
 
         // can we go further right?
         if let Some((nid, border, delta)) = neighbors[&current].get(&right_side) {
             let new_transform = *delta + current_transform;
-            let left_side = new_transform.get_side(1);
+            let left_side = get_side(&new_transform, 1);
             // println!(
             //     "right neighbor is {} with transform {:?}. left side is {}",
             //     nid, new_transform, left_side
             // );
 
             let (_lid, mut left_border, _lt) = neighbors[&nid][&left_side].clone();
-            if !delta.flip {
+            if !delta.is_flipped() {
                 left_border.tiles.reverse()
             }
 
@@ -281,7 +200,7 @@ fn solve(
                 }
 
                 for (k, t) in &[(current, current_transform), (*nid, new_transform)] {
-                    println!("{} {:?}\n{}", k, t, t.apply_to(&tiles[&k]))
+                    println!("{} {:?}\n{}", k, t, tiles[&k].transform(*t))
                 }
                 panic!(
                     "Border mismatch!\n{}-{} right border: {:?}\n{}-{}  left border: {:?}",
@@ -309,19 +228,19 @@ fn solve(
         // println!("next row!");
         // println!("{} {}: {} {:?}", i, j, current, current_transform);
 
-        let bottom_side = current_transform.get_side(2);
+        let bottom_side = get_side(&current_transform, 2);
 
         // can we go down from first element of row?
         if let Some((nid, border, delta)) = neighbors[&current].get(&bottom_side) {
             let new_transform = *delta + current_transform;
-            let top_side = new_transform.get_side(0);
+            let top_side = get_side(&new_transform, 0);
             // println!(
             //     "bottom neighbor is {} with transform {:?}. top side is {}",
             //     nid, new_transform, top_side
             // );
             let (_tid, mut top_border, _tt) = neighbors[&nid][&top_side].clone();
 
-            if !delta.flip {
+            if !delta.is_flipped() {
                 top_border.tiles.reverse();
             }
 
@@ -340,7 +259,7 @@ fn solve(
                 }
 
                 for (k, t) in &[(current, current_transform), (*nid, new_transform)] {
-                    println!("{} {:?}\n{}", k, t, t.apply_to(&tiles[&k]))
+                    println!("{} {:?}\n{}", k, t, tiles[&k].transform(*t))
                 }
                 panic!(
                     "Border mismatch!\n{}-{} bottom border: {:?}\n{}-{}   top border: {:?}",
@@ -371,60 +290,25 @@ fn join_all(
     padding: usize,
     cut: usize,
 ) -> Map<[usize; 2], Tile> {
-    let (emin, [th, tw]) = tiles[&solution[&(0, 0)].0].get_extent();
-    assert_eq!(emin[0], 0);
-    assert_eq!(emin[1], 0);
-
     let mut out = Map::new();
 
     for ((x, y), (tid, transform)) in solution.iter() {
-        let tile = transform.apply_to(&tiles[tid]);
-
-        for ([ti, tj], tile) in tile.data.iter() {
-            if *ti >= cut && *ti <= th - cut && *tj >= cut && *tj <= tw - cut {
-                out.set(
-                    [
-                        y * (th + 1 + padding - 2 * cut) + ti - cut,
-                        x * (tw + 1 + padding - 2 * cut) + tj - cut,
-                    ],
-                    tile.clone(),
-                );
-            }
-        }
+        let tile = tiles[tid].transform(*transform).strip_border(cut);
+        let Bounds { min, max } = tile.bounds();
+        let (th, tw) = (max[0] - min[0] + 1 + padding, max[1] - min[1] + 1 + padding);
+
+        out.paste(
+            &tile,
+            &[
+                (y * th) as i64 - min[0] as i64,
+                (x * tw) as i64 - min[1] as i64,
+            ],
+        );
     }
 
     out
 }
 
-fn find_monsters(map: &mut Map<[usize; 2], Tile>, pattern: &Map<[usize; 2], Tile>) -> usize {
-    let (min, max) = map.get_extent();
-    let (_, pattern_dim) = pattern.get_extent();
-
-    let mut n_found = 0;
-    for iofs in min[0]..=max[0] - pattern_dim[0] {
-        for jofs in min[1]..=max[1] - pattern_dim[1] {
-            let mut found = true;
-            for [pi, pj] in pattern.data.keys() {
-                match map.get(&[iofs + pi, jofs + pj]) {
-                    Some(Tile::Occupied) => {}
-                    _ => {
-                        found = false;
-                        break;
-                    }
-                }
-            }
-
-            if found {
-                n_found += 1;
-                for [pi, pj] in pattern.data.keys() {
-                    map.set([iofs + pi, jofs + pj], Tile::SeaMonster);
-                }
-            }
-        }
-    }
-    n_found
-}
-
 fn borders_to_neighbors(
     borders: &HashMap<Border, Vec<(usize, Transform)>>,
 ) -> HashMap<usize, HashMap<u8, (usize, Border, Transform)>> {
@@ -437,21 +321,13 @@ fn borders_to_neighbors(
                 }
 
                 // disambiguate by requiring that one of the transforms is not flipped
-                if transform1.flip {
+                if transform1.is_flipped() {
                     continue;
                 }
 
-                let side = if transform1.flip {
-                    4 - transform1.rotate
-                } else {
-                    transform1.rotate
-                } % 4;
+                let side = transform1.rotations();
 
-                let delta = *transform2
-                    + Transform {
-                        flip: true,
-                        rotate: 4 - side,
-                    };
+                let delta = *transform2 + Transform::new(true, 4 - side);
 
                 // println!(
                 //     "{} has {:?} on side {} with transform {:?} shared with {} with transform {:?} -> delta {:?}",
@@ -512,35 +388,29 @@ fn main() -> Result<()> {
     let top_left_corner = top_left_corner.expect("top left corner");
 
     let solution = solve(&neighbors, *top_left_corner, &tiles);
-    let map = join_all(&tiles, solution, 0, 1).flip(0);
-
-    let sea_monster: Map<[usize; 2], Tile> =
-        Map::read(&mut File::open("data/day20/sea_monster").context(Io)?).context(ParseMap)?;
-
-    let mut max_found = 0;
-    let mut max_transformed = map.clone();
-    for flip in &[false, true] {
-        for rotate in 0..=3 {
-            let transform = Transform {
-                flip: *flip,
-                rotate,
-            };
-
-            let mut transformed = transform.apply_to(&map);
-
-            let n_found = find_monsters(&mut transformed, &sea_monster);
-
-            if n_found > max_found {
-                max_found = n_found;
-                max_transformed = transformed;
-            }
-        }
-    }
-
-    println!("{}", max_transformed);
+    let mut map = join_all(&tiles, solution, 0, 1).flip(0);
+
+    let sea_monster: Map<[usize; 2], Tile> = MapReader::new()
+        .void_chars(" ")
+        .read(&mut File::open("data/day20/sea_monster").context(Io)?)
+        .context(ParseMap)?;
+
+    let monsters = map.find_pattern(&sea_monster, true);
+    map.overwrite_matches(&sea_monster, &monsters, Tile::SeaMonster);
+
+    let monster_coords = Map::match_coords(&sea_monster, &monsters);
+    let color = std::io::stdout().is_terminal();
+    let renderer = Renderer::new().color(color).legend(true).overlay(
+        Overlay::new(monster_coords)
+            .glyph("O")
+            .color(Color::Green)
+            .label("sea monster"),
+    );
+    println!("{}", renderer.render(&map));
+    println!("Found {} sea monsters", monsters.len());
 
     let mut n_waves = 0;
-    for t in max_transformed.data.values() {
+    for t in map.values() {
         if t == &Tile::Occupied {
             n_waves += 1;
         }
@@ -550,74 +420,3 @@ fn main() -> Result<()> {
 
     Ok(())
 }
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[derive(Debug, Clone, PartialEq, Eq)]
-    struct T(char);
-
-    impl std::fmt::Display for T {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            write!(f, "{}", self.0)
-        }
-    }
-
-    impl ParseMapTile for T {
-        fn from_char(c: char) -> Option<Self> {
-            if c == ' ' {
-                None
-            } else {
-                Some(T(c))
-            }
-        }
-    }
-
-    #[test]
-    fn test_addition() {
-        let map: Map<[usize; 2], T> = "abc\ndef\nghi".parse().unwrap();
-
-        for flip_a in &[false, true] {
-            for rot_a in 0..=3 {
-                let a = Transform {
-                    flip: *flip_a,
-                    rotate: rot_a,
-                };
-                for flip_b in &[false, true] {
-                    for rot_b in 0..=3 {
-                        let b = Transform {
-                            flip: *flip_b,
-                            rotate: rot_b,
-                        };
-
-                        println!("{:?} {:?}", a, b);
-
-                        assert_eq!(b.apply_to(&a.apply_to(&map)), (a + b).apply_to(&map));
-                    }
-                }
-            }
-        }
-    }
-
-    #[test]
-    fn test_neutral() {
-        for flip_a in &[false, true] {
-            for rot_a in 0..=3 {
-                let a = Transform {
-                    flip: *flip_a,
-                    rotate: rot_a,
-                };
-                println!("{:?}", a);
-
-                assert_eq!(
-                    a + (-a),
-                    Transform {
-                        flip: false,
-                        rotate: 0
-                    }
-                );
-            }
-        }
-    }
-}
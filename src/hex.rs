@@ -0,0 +1,423 @@
+use snafu::Snafu;
+
+use crate::map::{
+    array_extent_index, array_extent_len, array_on_boundary, Bounds, ExtentIterator, Map,
+    MapCoordinate, MapTile, Neighborhood, Topology,
+};
+
+#[derive(Debug, Snafu)]
+pub enum HexError {
+    #[snafu(display("Cannot parse hex direction: '{}'", data))]
+    ParseDirection { data: String },
+}
+
+type HexResult<T> = std::result::Result<T, HexError>;
+
+/// One of the six directions on a hexagonal grid with pointy-topped hexagons
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HexDirection {
+    East,
+    SouthEast,
+    SouthWest,
+    West,
+    NorthWest,
+    NorthEast,
+}
+
+impl HexDirection {
+    /// All directions, clockwise starting from east
+    pub fn all() -> [HexDirection; 6] {
+        [
+            HexDirection::East,
+            HexDirection::SouthEast,
+            HexDirection::SouthWest,
+            HexDirection::West,
+            HexDirection::NorthWest,
+            HexDirection::NorthEast,
+        ]
+    }
+
+    /// Get the offset of a single step in this direction
+    pub fn offset(&self) -> Hex {
+        let (q, r) = match self {
+            HexDirection::East => (1, 0),
+            HexDirection::SouthEast => (0, 1),
+            HexDirection::SouthWest => (-1, 1),
+            HexDirection::West => (-1, 0),
+            HexDirection::NorthWest => (0, -1),
+            HexDirection::NorthEast => (1, -1),
+        };
+
+        Hex { q, r }
+    }
+
+    /// Parse a path of concatenated `e`, `se`, `sw`, `w`, `nw` and `ne` steps
+    pub fn parse_path(s: &str) -> HexResult<Vec<HexDirection>> {
+        let mut out = Vec::new();
+
+        let mut s = s.chars();
+        while let Some(c) = s.next() {
+            out.push(match c {
+                'e' => HexDirection::East,
+                'w' => HexDirection::West,
+                'n' | 's' => {
+                    let d = s.next().ok_or_else(|| HexError::ParseDirection {
+                        data: format!("'{}' without character after", c),
+                    })?;
+
+                    match (c, d) {
+                        ('n', 'w') => HexDirection::NorthWest,
+                        ('n', 'e') => HexDirection::NorthEast,
+                        ('s', 'w') => HexDirection::SouthWest,
+                        ('s', 'e') => HexDirection::SouthEast,
+                        _ => {
+                            return Err(HexError::ParseDirection {
+                                data: format!("{}{}", c, d),
+                            })
+                        }
+                    }
+                }
+                _ => {
+                    return Err(HexError::ParseDirection {
+                        data: format!("{}", c),
+                    })
+                }
+            });
+        }
+
+        Ok(out)
+    }
+}
+
+/// Position on a hexagonal grid in axial coordinates.
+///
+/// Hexagons are pointy-topped, so that `q` increases towards the east and `r` increases
+/// towards the south-east. Rows of constant `r` are staggered when rendered.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Hex {
+    pub q: i64,
+    pub r: i64,
+}
+
+impl Hex {
+    pub fn new(q: i64, r: i64) -> Self {
+        Hex { q, r }
+    }
+
+    /// Convert from cube coordinates, which must sum up to zero
+    pub fn from_cube([x, y, z]: [i64; 3]) -> Self {
+        assert_eq!(x + y + z, 0, "Cube coordinates must sum up to zero");
+        Hex { q: x, r: z }
+    }
+
+    /// Convert to cube coordinates `[x, y, z]` with `x + y + z == 0`
+    pub fn to_cube(&self) -> [i64; 3] {
+        [self.q, -self.q - self.r, self.r]
+    }
+
+    /// Convert from "odd-r" offset coordinates `[row, column]`, where odd rows are
+    /// shifted right by half a hexagon
+    pub fn from_offset([row, col]: [i64; 2]) -> Self {
+        Hex {
+            q: col - (row - (row & 1)) / 2,
+            r: row,
+        }
+    }
+
+    /// Convert to "odd-r" offset coordinates `[row, column]`
+    pub fn to_offset(&self) -> [i64; 2] {
+        [self.r, self.q + (self.r - (self.r & 1)) / 2]
+    }
+
+    /// Get the number of steps needed to get from one hexagon to another
+    pub fn distance(&self, other: &Hex) -> i64 {
+        let [x, y, z] = (*self - *other).to_cube();
+        (x.abs() + y.abs() + z.abs()) / 2
+    }
+
+    /// Get the adjacent hexagon in a direction
+    pub fn step(&self, direction: HexDirection) -> Hex {
+        *self + direction.offset()
+    }
+
+    /// Iterate over the six adjacent hexagons, clockwise starting from east
+    pub fn adjacent(&self) -> impl Iterator<Item = Hex> {
+        let center = *self;
+        IntoIterator::into_iter(HexDirection::all()).map(move |d| center.step(d))
+    }
+
+    /// Get all hexagons at exactly a distance from this one
+    pub fn ring(&self, radius: usize) -> Vec<Hex> {
+        if radius == 0 {
+            return vec![*self];
+        }
+
+        // start in the south-west corner and walk around the ring clockwise
+        let mut current = *self + HexDirection::SouthWest.offset() * radius as i64;
+        let mut out = Vec::with_capacity(6 * radius);
+        for direction in &[
+            HexDirection::NorthWest,
+            HexDirection::NorthEast,
+            HexDirection::East,
+            HexDirection::SouthEast,
+            HexDirection::SouthWest,
+            HexDirection::West,
+        ] {
+            for _ in 0..radius {
+                out.push(current);
+                current = current.step(*direction);
+            }
+        }
+        out
+    }
+}
+
+impl std::ops::Add for Hex {
+    type Output = Hex;
+
+    fn add(self, rhs: Hex) -> Hex {
+        Hex {
+            q: self.q + rhs.q,
+            r: self.r + rhs.r,
+        }
+    }
+}
+
+impl std::ops::Sub for Hex {
+    type Output = Hex;
+
+    fn sub(self, rhs: Hex) -> Hex {
+        Hex {
+            q: self.q - rhs.q,
+            r: self.r - rhs.r,
+        }
+    }
+}
+
+impl std::ops::Mul<i64> for Hex {
+    type Output = Hex;
+
+    fn mul(self, rhs: i64) -> Hex {
+        Hex {
+            q: self.q * rhs,
+            r: self.r * rhs,
+        }
+    }
+}
+
+impl std::str::FromStr for Hex {
+    type Err = HexError;
+
+    /// Parse a path of steps starting from the origin
+    fn from_str(s: &str) -> HexResult<Self> {
+        Ok(HexDirection::parse_path(s)?
+            .into_iter()
+            .fold(Hex::default(), |pos, d| pos.step(d)))
+    }
+}
+
+impl MapCoordinate for Hex {
+    type ExtentIter = std::iter::Map<ExtentIterator<i64, 2>, fn([i64; 2]) -> Hex>;
+    type Offset = Hex;
+
+    fn origin() -> Self {
+        Hex::default()
+    }
+
+    fn elementwise_min(a: Self, b: Self) -> Self {
+        Hex {
+            q: std::cmp::min(a.q, b.q),
+            r: std::cmp::min(a.r, b.r),
+        }
+    }
+
+    fn elementwise_max(a: Self, b: Self) -> Self {
+        Hex {
+            q: std::cmp::max(a.q, b.q),
+            r: std::cmp::max(a.r, b.r),
+        }
+    }
+
+    fn extent_iterator(min: Self, max: Self) -> Self::ExtentIter {
+        <[i64; 2]>::extent_iterator([min.q, min.r], [max.q, max.r]).map(|[q, r]| Hex { q, r })
+    }
+
+    fn extent_len(min: Self, max: Self) -> usize {
+        array_extent_len(&[min.q, min.r], &[max.q, max.r])
+    }
+
+    fn extent_index(&self, min: Self, max: Self) -> Option<usize> {
+        array_extent_index(&[self.q, self.r], &[min.q, min.r], &[max.q, max.r])
+    }
+
+    fn on_boundary(&self, min: Self, max: Self) -> bool {
+        array_on_boundary(&[self.q, self.r], &[min.q, min.r], &[max.q, max.r])
+    }
+
+    /// On a hex grid, all six adjacent hexagons share an edge, so both Moore and
+    /// von Neumann neighborhoods contain all hexagons up to the radius in distance.
+    fn neighbor_offsets(neighborhood: Neighborhood) -> Vec<Self::Offset> {
+        let radius = match neighborhood {
+            Neighborhood::Moore(r) | Neighborhood::VonNeumann(r) => r,
+        };
+
+        (1..=radius).flat_map(|r| Hex::default().ring(r)).collect()
+    }
+
+    fn offset(&self, by: &Self::Offset) -> Option<Self> {
+        Some(*self + *by)
+    }
+
+    /// The axes of the topology are the axial `q` and `r` coordinates, so wrapping
+    /// turns the extent into a rhombus-shaped torus
+    fn offset_within(
+        &self,
+        by: &Self::Offset,
+        bounds: &Bounds<Self>,
+        topology: &[Topology],
+    ) -> Option<Self> {
+        let bounds = Bounds::new([bounds.min.q, bounds.min.r], [bounds.max.q, bounds.max.r]);
+        let [q, r] = [self.q, self.r].offset_within(&[by.q, by.r], &bounds, topology)?;
+        Some(Hex::new(q, r))
+    }
+
+    fn wrap(&self, bounds: &Bounds<Self>, topology: &[Topology]) -> Option<Self> {
+        self.offset_within(&Hex::default(), bounds, topology)
+    }
+
+    fn chunk_len(size: usize) -> usize {
+        <[i64; 2]>::chunk_len(size)
+    }
+
+    fn chunk_split(&self, size: usize) -> Option<(Self, usize)> {
+        let ([q, r], index) = [self.q, self.r].chunk_split(size)?;
+        Some((Hex::new(q, r), index))
+    }
+
+    fn chunk_join(chunk: Self, index: usize, size: usize) -> Self {
+        let [q, r] = <[i64; 2]>::chunk_join([chunk.q, chunk.r], index, size);
+        Hex::new(q, r)
+    }
+}
+
+impl<T: Clone> Map<Hex, T> {
+    /// Convert into a rectangular map indexed by odd-r offset coordinates `[row, col]`
+    pub fn to_offset_map(&self) -> Map<[i64; 2], T> {
+        self.iter()
+            .map(|(h, t)| (h.to_offset(), t.clone()))
+            .collect::<std::collections::HashMap<_, _>>()
+            .into()
+    }
+}
+
+impl<T: MapTile> std::fmt::Display for Map<Hex, T> {
+    /// Render as a staggered grid where odd rows are shifted right by half a tile
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
+        let Bounds { min, max } = match Bounds::from_coords(self.keys().map(|h| h.to_offset())) {
+            Some(bounds) => bounds,
+            None => return Ok(()),
+        };
+
+        for row in min[0]..=max[0] {
+            if row & 1 == 1 {
+                write!(f, " ")?;
+            }
+
+            for col in min[1]..=max[1] {
+                if col > min[1] {
+                    write!(f, " ")?;
+                }
+
+                match self.get(&Hex::from_offset([row, col])) {
+                    Some(t) => t.fmt(f),
+                    None => write!(f, " "),
+                }?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!("esew".parse::<Hex>().unwrap(), Hex::new(0, 1));
+        assert_eq!("nwwswee".parse::<Hex>().unwrap(), Hex::new(0, 0));
+        assert_eq!(
+            HexDirection::parse_path("nesw").unwrap(),
+            vec![HexDirection::NorthEast, HexDirection::SouthWest]
+        );
+        assert!("en".parse::<Hex>().is_err());
+        assert!("ex".parse::<Hex>().is_err());
+    }
+
+    #[test]
+    fn test_conversions() {
+        for q in -3..=3 {
+            for r in -3..=3 {
+                let h = Hex::new(q, r);
+                assert_eq!(Hex::from_cube(h.to_cube()), h);
+                assert_eq!(Hex::from_offset(h.to_offset()), h);
+            }
+        }
+
+        assert_eq!(Hex::new(0, 1).to_offset(), [1, 0]);
+        assert_eq!(Hex::new(-1, 1).to_offset(), [1, -1]);
+        assert_eq!(Hex::new(1, -1).to_offset(), [-1, 0]);
+    }
+
+    #[test]
+    fn test_distance_and_rings() {
+        let origin = Hex::default();
+        assert_eq!(origin.distance(&Hex::new(3, -1)), 3);
+        assert_eq!(Hex::new(-2, 2).distance(&Hex::new(1, 1)), 3);
+
+        assert_eq!(origin.ring(0), vec![origin]);
+        for radius in 1..=4 {
+            let ring = origin.ring(radius);
+            assert_eq!(ring.len(), 6 * radius);
+            assert!(ring.iter().all(|h| origin.distance(h) == radius as i64));
+        }
+
+        let mut adjacent: Vec<Hex> = origin.adjacent().collect();
+        let mut ring = origin.ring(1);
+        adjacent.sort();
+        ring.sort();
+        assert_eq!(adjacent, ring);
+
+        assert_eq!(origin.neighbors(Neighborhood::Moore(1)).len(), 6);
+        assert_eq!(origin.neighbors(Neighborhood::VonNeumann(2)).len(), 18);
+    }
+
+    #[test]
+    fn test_map() {
+        let mut map: Map<Hex, char> = Map::new();
+        map.set(Hex::new(0, 0), 'a');
+        map.set(Hex::new(1, 0), 'b');
+        map.set(Hex::new(0, 1), 'c');
+        map.set(Hex::new(-1, 1), 'd');
+
+        assert_eq!(map.count_neighbors_where(&Hex::new(0, 0), |_, _| true), 3);
+        assert_eq!(map.count_neighbors_where(&Hex::new(1, 1), |_, _| true), 2);
+        assert_eq!(format!("{}", map), "  a b\n d c  \n");
+
+        let offset = map.to_offset_map();
+        assert_eq!(offset.len(), 4);
+        assert_eq!(offset.get(&[1, -1]), Some(&'d'));
+        assert_eq!(offset.get(&[0, 1]), Some(&'b'));
+
+        map.to_chunked(2);
+        assert!(map.is_chunked());
+        assert_eq!(map.get(&Hex::new(-1, 1)), Some(&'d'));
+        assert_eq!(map.len(), 4);
+
+        map.to_dense();
+        assert_eq!(map.get(&Hex::new(-1, 1)), Some(&'d'));
+        assert_eq!(map.get(&Hex::new(1, 1)), None);
+    }
+}
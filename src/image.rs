@@ -0,0 +1,516 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use snafu::{ensure, ResultExt, Snafu};
+
+use crate::map::{Bounds, IntCoord, Map};
+
+#[derive(Debug, Snafu)]
+pub enum ImageError {
+    #[snafu(display("I/O error on '{}': {}", filename.display(), source))]
+    Io {
+        filename: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Cannot write image: {}", source))]
+    Encode { source: std::io::Error },
+
+    #[snafu(display("Cannot write an image of an empty map"))]
+    EmptyMap,
+
+    #[snafu(display("Image scale must be at least 1"))]
+    ZeroScale,
+
+    #[snafu(display("Image of {}x{} tiles at scale {} is too large", rows, cols, scale))]
+    TooLarge {
+        rows: usize,
+        cols: usize,
+        scale: usize,
+    },
+}
+
+pub type ImageResult<T> = std::result::Result<T, ImageError>;
+
+/// Trait for tiles that can be drawn as colored pixels
+pub trait TileColor {
+    /// Get the RGB color of a tile
+    fn color(&self) -> [u8; 3];
+
+    /// Get the RGB color of undefined cells
+    fn background() -> [u8; 3] {
+        [0, 0, 0]
+    }
+}
+
+/// Supported image formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Ppm,
+    Png,
+    Svg,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Ppm => "ppm",
+            ImageFormat::Png => "png",
+            ImageFormat::Svg => "svg",
+        }
+    }
+}
+
+/// An RGB raster image
+struct Raster {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl<T, I> Map<[I; 2], T>
+where
+    T: TileColor,
+    I: IntCoord,
+{
+    /// Get the width and height in pixels of an image of the map at a scale, checking
+    /// that it is neither empty nor too large to encode
+    fn image_size(&self, scale: usize) -> ImageResult<(usize, usize)> {
+        ensure!(!self.is_empty() || self.fixed_extent.is_some(), EmptyMap);
+        ensure!(scale > 0, ZeroScale);
+
+        let Bounds { min, max } = self.bounds();
+        let rows = (max[0] - min[0]).to_usize().unwrap_or(0) + 1;
+        let cols = (max[1] - min[1]).to_usize().unwrap_or(0) + 1;
+        // PNG stores the dimensions as 32 bit integers and all image data in one chunk,
+        // whose length must not exceed 2^31 - 1 bytes
+        let fits = |n: usize| n.checked_mul(scale).filter(|&n| n <= u32::MAX as usize);
+        match (fits(cols), fits(rows)) {
+            (Some(width), Some(height))
+                if (width * 3 + 1)
+                    .checked_mul(height)
+                    .filter(|&n| n <= i32::MAX as usize)
+                    .is_some_and(|n| zlib_stored_len(n) <= i32::MAX as usize) =>
+            {
+                Ok((width, height))
+            }
+            _ => TooLarge { rows, cols, scale }.fail(),
+        }
+    }
+
+    /// Draw the map with every tile covering `scale` by `scale` pixels. Rows of the image
+    /// correspond to axis 0 and columns to axis 1.
+    fn rasterize(&self, scale: usize) -> ImageResult<Raster> {
+        let (width, height) = self.image_size(scale)?;
+        let Bounds { min, max } = self.bounds();
+
+        let background = T::background();
+        let mut pixels = Vec::with_capacity(width * height * 3);
+        for (r, i) in num::iter::range_inclusive(min[0], max[0]).enumerate() {
+            let mut row = Vec::with_capacity(width * 3);
+            for j in num::iter::range_inclusive(min[1], max[1]) {
+                let color = self.get(&[i, j]).map_or(background, |t| t.color());
+                for _ in 0..scale {
+                    row.extend_from_slice(&color);
+                }
+            }
+
+            for _ in 0..scale {
+                pixels.extend_from_slice(&row);
+            }
+
+            debug_assert_eq!(pixels.len(), (r + 1) * scale * width * 3);
+        }
+
+        Ok(Raster {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Write the map as a binary PPM image
+    pub fn write_ppm<W: Write>(&self, writer: &mut W, scale: usize) -> ImageResult<()> {
+        let raster = self.rasterize(scale)?;
+
+        write!(writer, "P6\n{} {}\n255\n", raster.width, raster.height).context(Encode)?;
+        writer.write_all(&raster.pixels).context(Encode)
+    }
+
+    /// Write the map as a PNG image. The image data is stored uncompressed.
+    pub fn write_png<W: Write>(&self, writer: &mut W, scale: usize) -> ImageResult<()> {
+        let raster = self.rasterize(scale)?;
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&(raster.width as u32).to_be_bytes());
+        header.extend_from_slice(&(raster.height as u32).to_be_bytes());
+        // 8 bits per channel, RGB, deflate, no filtering, no interlacing
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        let mut scanlines = Vec::with_capacity((raster.width * 3 + 1) * raster.height);
+        for row in raster.pixels.chunks(raster.width * 3) {
+            scanlines.push(0);
+            scanlines.extend_from_slice(row);
+        }
+
+        writer
+            .write_all(&[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'])
+            .context(Encode)?;
+        write_png_chunk(writer, b"IHDR", &header)?;
+        write_png_chunk(writer, b"IDAT", &zlib_stored(&scanlines))?;
+        write_png_chunk(writer, b"IEND", &[])
+    }
+
+    /// Write the map as an SVG image with one square per defined tile
+    pub fn write_svg<W: Write>(&self, writer: &mut W, scale: usize) -> ImageResult<()> {
+        let (width, height) = self.image_size(scale)?;
+        let Bounds { min, max } = self.bounds();
+
+        writeln!(
+            writer,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">",
+            w = width,
+            h = height
+        )
+        .context(Encode)?;
+        writeln!(
+            writer,
+            "  <rect width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+            width,
+            height,
+            hex_color(T::background())
+        )
+        .context(Encode)?;
+
+        for (r, i) in num::iter::range_inclusive(min[0], max[0]).enumerate() {
+            for (c, j) in num::iter::range_inclusive(min[1], max[1]).enumerate() {
+                if let Some(t) = self.get(&[i, j]) {
+                    writeln!(
+                        writer,
+                        "  <rect x=\"{}\" y=\"{}\" width=\"{s}\" height=\"{s}\" fill=\"{}\"/>",
+                        c * scale,
+                        r * scale,
+                        hex_color(t.color()),
+                        s = scale
+                    )
+                    .context(Encode)?;
+                }
+            }
+        }
+
+        writeln!(writer, "</svg>").context(Encode)
+    }
+
+    /// Write the map in an image format
+    pub fn write_image<W: Write>(
+        &self,
+        writer: &mut W,
+        format: ImageFormat,
+        scale: usize,
+    ) -> ImageResult<()> {
+        match format {
+            ImageFormat::Ppm => self.write_ppm(writer, scale),
+            ImageFormat::Png => self.write_png(writer, scale),
+            ImageFormat::Svg => self.write_svg(writer, scale),
+        }
+    }
+
+    /// Save the map to an image file
+    pub fn save_image<P: AsRef<Path>>(
+        &self,
+        path: P,
+        format: ImageFormat,
+        scale: usize,
+    ) -> ImageResult<()> {
+        let path = path.as_ref();
+        let mut f = std::io::BufWriter::new(std::fs::File::create(path).context(Io {
+            filename: path.to_path_buf(),
+        })?);
+
+        self.write_image(&mut f, format, scale)?;
+        f.flush().context(Io {
+            filename: path.to_path_buf(),
+        })
+    }
+}
+
+/// Writes successive states of a simulation as numbered image files, e.g. to be
+/// assembled into an animation.
+///
+/// All frames should have the same extent, which can be ensured by setting
+/// `fixed_extent` on the maps.
+pub struct FrameWriter {
+    directory: PathBuf,
+    prefix: String,
+    format: ImageFormat,
+    scale: usize,
+    frame: usize,
+}
+
+impl FrameWriter {
+    pub fn new<P: Into<PathBuf>>(
+        directory: P,
+        prefix: &str,
+        format: ImageFormat,
+        scale: usize,
+    ) -> Self {
+        FrameWriter {
+            directory: directory.into(),
+            prefix: prefix.to_string(),
+            format,
+            scale,
+            frame: 0,
+        }
+    }
+
+    /// Create a frame writer if the `AOC_FRAMES` environment variable points to an
+    /// output directory
+    pub fn from_env(prefix: &str) -> Option<Self> {
+        let directory = std::env::var_os("AOC_FRAMES")?;
+        Some(FrameWriter::new(directory, prefix, ImageFormat::Png, 4))
+    }
+
+    /// Get the number of frames written so far
+    pub fn frames(&self) -> usize {
+        self.frame
+    }
+
+    /// Write the next frame, returning the path of the written file
+    pub fn write_frame<T, I>(&mut self, map: &Map<[I; 2], T>) -> ImageResult<PathBuf>
+    where
+        T: TileColor,
+        I: IntCoord,
+    {
+        std::fs::create_dir_all(&self.directory).context(Io {
+            filename: self.directory.clone(),
+        })?;
+
+        let path = self.directory.join(format!(
+            "{}{:05}.{}",
+            self.prefix,
+            self.frame,
+            self.format.extension()
+        ));
+
+        map.save_image(&path, self.format, self.scale)?;
+        self.frame += 1;
+
+        Ok(path)
+    }
+}
+
+fn hex_color([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn write_png_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> ImageResult<()> {
+    debug_assert!(data.len() <= i32::MAX as usize, "PNG chunk too long");
+    let mut crc = Crc32::new();
+    crc.update(kind);
+    crc.update(data);
+
+    writer
+        .write_all(&(data.len() as u32).to_be_bytes())
+        .context(Encode)?;
+    writer.write_all(kind).context(Encode)?;
+    writer.write_all(data).context(Encode)?;
+    writer
+        .write_all(&crc.finish().to_be_bytes())
+        .context(Encode)
+}
+
+/// Get the length of the output of `zlib_stored` for some number of bytes
+fn zlib_stored_len(n: usize) -> usize {
+    // header and checksum, 5 bytes of framing per block of up to 0xffff bytes
+    2 + n + 5 * std::cmp::max(n.div_ceil(0xffff), 1) + 4
+}
+
+/// Wrap data into a zlib stream made of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 0xffff;
+
+    let mut out = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[0x01, 0x00, 0x00, 0xff, 0xff]);
+    }
+
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+
+        out.push(if last { 0x01 } else { 0x00 });
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    // Adler-32 checksum of the uncompressed data
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    out.extend_from_slice(&((b << 16) | a).to_be_bytes());
+
+    out
+}
+
+/// CRC-32 as used by PNG
+struct Crc32 {
+    value: u32,
+}
+
+impl Crc32 {
+    fn new() -> Self {
+        Crc32 { value: 0xffff_ffff }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.value ^= *byte as u32;
+            for _ in 0..8 {
+                let mask = (!(self.value & 1)).wrapping_add(1);
+                self.value = (self.value >> 1) ^ (0xedb8_8320 & mask);
+            }
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        !self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Gray(u8);
+
+    impl TileColor for Gray {
+        fn color(&self) -> [u8; 3] {
+            [self.0, self.0, self.0]
+        }
+
+        fn background() -> [u8; 3] {
+            [255, 0, 0]
+        }
+    }
+
+    fn sample() -> Map<[i64; 2], Gray> {
+        let mut map = Map::new();
+        map.set([-1, 0], Gray(10));
+        map.set([0, 1], Gray(20));
+        map
+    }
+
+    #[test]
+    fn test_ppm() {
+        let mut out = Vec::new();
+        sample().write_ppm(&mut out, 1).unwrap();
+
+        let mut expected = b"P6\n2 2\n255\n".to_vec();
+        expected.extend_from_slice(&[10, 10, 10, 255, 0, 0, 255, 0, 0, 20, 20, 20]);
+        assert_eq!(out, expected);
+
+        let mut scaled = Vec::new();
+        sample().write_ppm(&mut scaled, 3).unwrap();
+        assert!(scaled.starts_with(b"P6\n6 6\n255\n"));
+        assert_eq!(scaled.len(), 11 + 6 * 6 * 3);
+    }
+
+    #[test]
+    fn test_png() {
+        let mut out = Vec::new();
+        sample().write_png(&mut out, 2).unwrap();
+
+        assert_eq!(
+            &out[..8],
+            &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n']
+        );
+        assert_eq!(&out[12..16], b"IHDR");
+        assert_eq!(&out[16..24], &[0, 0, 0, 4, 0, 0, 0, 4]);
+        assert_eq!(&out[out.len() - 8..out.len() - 4], b"IEND");
+        // CRC of an empty IEND chunk
+        assert_eq!(&out[out.len() - 4..], &[0xae, 0x42, 0x60, 0x82]);
+
+        let empty: Map<[i64; 2], Gray> = Map::new();
+        assert!(matches!(
+            empty.write_png(&mut Vec::new(), 1),
+            Err(ImageError::EmptyMap)
+        ));
+        assert!(matches!(
+            sample().write_png(&mut Vec::new(), 0),
+            Err(ImageError::ZeroScale)
+        ));
+
+        let mut huge: Map<[i64; 2], Gray> = Map::new();
+        huge.set([0, 0], Gray(0));
+        huge.set([0, 1 << 40], Gray(0));
+        assert!(matches!(
+            huge.write_png(&mut Vec::new(), 1),
+            Err(ImageError::TooLarge { rows: 1, .. })
+        ));
+
+        // fits the 32 bit dimensions, but not the 2^31 - 1 byte limit of a chunk
+        let mut wide: Map<[i64; 2], Gray> = Map::new();
+        wide.set([0, 0], Gray(0));
+        wide.set([0, (1 << 30) - 1], Gray(0));
+        assert!(matches!(
+            wide.write_png(&mut Vec::new(), 1),
+            Err(ImageError::TooLarge { rows: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_zlib() {
+        let data: Vec<u8> = (0..70000u32).map(|i| (i % 251) as u8).collect();
+        let z = zlib_stored(&data);
+
+        // header, two blocks with 5 bytes of framing each, checksum
+        assert_eq!(z.len(), 2 + data.len() + 2 * 5 + 4);
+        assert_eq!(z.len(), zlib_stored_len(data.len()));
+        assert_eq!(zlib_stored(&[]).len(), zlib_stored_len(0));
+        assert_eq!(z[2], 0x00);
+        assert_eq!(z[2 + 5 + 0xffff], 0x01);
+        assert_eq!(&zlib_stored(b"abc")[10..], &[0x02, 0x4d, 0x01, 0x27]);
+    }
+
+    #[test]
+    fn test_svg() {
+        let mut out = Vec::new();
+        sample().write_svg(&mut out, 5).unwrap();
+        let svg = String::from_utf8(out).unwrap();
+
+        assert!(
+            svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"10\" height=\"10\"")
+        );
+        assert!(svg.contains("<rect width=\"10\" height=\"10\" fill=\"#ff0000\"/>"));
+        assert!(svg.contains("<rect x=\"0\" y=\"0\" width=\"5\" height=\"5\" fill=\"#0a0a0a\"/>"));
+        assert!(svg.contains("<rect x=\"5\" y=\"5\" width=\"5\" height=\"5\" fill=\"#141414\"/>"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn test_frames() {
+        let dir = std::env::temp_dir().join(format!("aoc2020-frames-{}", std::process::id()));
+        let mut frames = FrameWriter::new(&dir, "step", ImageFormat::Ppm, 1);
+
+        let mut map = sample();
+        map.fixed_extent = Some(Bounds::new([-1, 0], [1, 1]));
+        let first = frames.write_frame(&map).unwrap();
+        map.set([1, 0], Gray(30));
+        let second = frames.write_frame(&map).unwrap();
+
+        assert_eq!(frames.frames(), 2);
+        assert_eq!(first, dir.join("step00000.ppm"));
+        assert_eq!(second, dir.join("step00001.ppm"));
+        assert!(std::fs::read(&second)
+            .unwrap()
+            .starts_with(b"P6\n2 3\n255\n"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,3 +1,12 @@
+pub mod automaton;
 pub mod code;
+pub mod hex;
+pub mod image;
 pub mod map;
+pub mod parallel;
+pub mod pattern;
+pub mod region;
+pub mod render;
 pub mod search;
+pub mod transform;
+pub mod view;
@@ -1,12 +1,36 @@
-use std::collections::HashMap;
+use std::collections::{hash_map, HashMap, HashSet};
+use std::sync::{Arc, OnceLock};
 
-use snafu::{ResultExt, Snafu};
+use snafu::{ensure, ResultExt, Snafu};
+
+use crate::render::{Color, Overlay, Renderer};
+use crate::transform::Transform;
 use std::io::{BufRead, BufReader};
 
 #[derive(Debug, Snafu)]
 pub enum MapError {
     #[snafu(display("I/O error: {}", source))]
     Io { source: std::io::Error },
+
+    #[snafu(display("Unknown tile {:?} at line {}, column {}", c, line, column))]
+    UnknownTile { c: char, line: usize, column: usize },
+
+    #[snafu(display(
+        "Coordinate of line {}, column {} does not fit the coordinate type",
+        line,
+        column
+    ))]
+    CoordinateOverflow { line: usize, column: usize },
+
+    #[snafu(display("Line {} has {} columns, expected {}", line, found, expected))]
+    RaggedRow {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+
+    #[snafu(display("Map input is empty"))]
+    EmptyInput,
 }
 
 type MapResult<T> = std::result::Result<T, MapError>;
@@ -46,10 +70,109 @@ pub trait ParseMapTile: MapTile {
     fn from_char(c: char) -> Option<Self>;
 }
 
+/// Shape of the neighborhood around a coordinate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Neighborhood {
+    /// All coordinates that differ by at most the given radius along every axis
+    Moore(usize),
+
+    /// All coordinates within the given Manhattan distance
+    VonNeumann(usize),
+}
+
+impl Default for Neighborhood {
+    fn default() -> Self {
+        Neighborhood::Moore(1)
+    }
+}
+
+/// Behavior of a map axis at the edges of the map's extent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Topology {
+    /// There are no tiles outside of the extent
+    Bounded,
+
+    /// Leaving the extent at one edge enters it at the opposite edge, like on a torus
+    Wrap,
+
+    /// Coordinates are not restricted
+    #[default]
+    Infinite,
+}
+
+/// Get all non-zero offset vectors in a neighborhood, with the last axis varying fastest
+fn neighborhood_offsets<const N: usize>(neighborhood: Neighborhood) -> Vec<[i64; N]> {
+    let (radius, max_manhattan) = match neighborhood {
+        Neighborhood::Moore(r) => (r as i64, None),
+        Neighborhood::VonNeumann(r) => (r as i64, Some(r as i64)),
+    };
+
+    let mut out = Vec::new();
+    let mut ofs = [-radius; N];
+    loop {
+        if ofs.iter().any(|d| *d != 0)
+            && max_manhattan.is_none_or(|m| ofs.iter().map(|d| d.abs()).sum::<i64>() <= m)
+        {
+            out.push(ofs);
+        }
+
+        // advance like an odometer, stopping once every axis has wrapped around
+        let mut axis = N;
+        loop {
+            if axis == 0 {
+                return out;
+            }
+            axis -= 1;
+            if ofs[axis] < radius {
+                ofs[axis] += 1;
+                break;
+            }
+            ofs[axis] = -radius;
+        }
+    }
+}
+
+/// Translate an array coordinate by an offset vector
+fn array_offset<I: IntCoord>(coord: &[I], by: &[i64], out: &mut [I]) -> Option<()> {
+    for ((o, c), d) in out.iter_mut().zip(coord.iter()).zip(by.iter()) {
+        *o = I::from_i64(c.to_i64()? + d)?;
+    }
+    Some(())
+}
+
+/// Translate an array coordinate by an offset and apply a topology relative to an extent
+/// to each axis. Axes without a topology are infinite.
+pub(crate) fn array_offset_within<I: IntCoord>(
+    coord: &[I],
+    by: &[i64],
+    bounds: (&[I], &[I]),
+    topology: &[Topology],
+    out: &mut [I],
+) -> Option<()> {
+    let (min, max) = bounds;
+    for (k, o) in out.iter_mut().enumerate() {
+        let mut c = coord[k].to_i64()? + by[k];
+        let (lo, hi) = (min[k].to_i64()?, max[k].to_i64()?);
+        match topology.get(k).copied().unwrap_or_default() {
+            Topology::Bounded if c < lo || c > hi => return None,
+            Topology::Wrap => c = lo + (c - lo).rem_euclid(hi - lo + 1),
+            _ => {}
+        }
+        *o = I::from_i64(c)?;
+    }
+    Some(())
+}
+
 /// Trait for a generic map coordinate
-pub trait MapCoordinate: Default + Eq + std::hash::Hash + std::fmt::Debug + Clone + Copy {
+pub trait MapCoordinate: Eq + std::hash::Hash + std::fmt::Debug + Clone + Copy {
     type ExtentIter: Iterator<Item = Self>;
 
+    /// Signed difference between two coordinates
+    type Offset: Copy + std::fmt::Debug + Send + Sync;
+
+    /// Get the coordinate at the origin. Used as the extent of empty maps.
+    fn origin() -> Self;
+
     /// Calculate the element-wise minimum of two coordinates.
     /// Used for computing the extent of the map.
     fn elementwise_min(a: Self, b: Self) -> Self;
@@ -58,9 +181,9 @@ pub trait MapCoordinate: Default + Eq + std::hash::Hash + std::fmt::Debug + Clon
     /// Used for computing the extent of the map.
     fn elementwise_max(a: Self, b: Self) -> Self;
 
-    fn get_extent<'a>(mut keys: impl Iterator<Item = Self>) -> (Self, Self) {
-        let mut min = keys.next().unwrap_or(Default::default());
-        let mut max = min.clone();
+    fn get_extent(mut keys: impl Iterator<Item = Self>) -> (Self, Self) {
+        let mut min = keys.next().unwrap_or_else(Self::origin);
+        let mut max = min;
 
         for k in keys {
             min = MapCoordinate::elementwise_min(min, k);
@@ -72,635 +195,1671 @@ pub trait MapCoordinate: Default + Eq + std::hash::Hash + std::fmt::Debug + Clon
 
     /// Get an iterator spanning all coordinates within an extent
     fn extent_iterator(min: Self, max: Self) -> Self::ExtentIter;
+
+    /// Get the number of coordinates within an extent
+    fn extent_len(min: Self, max: Self) -> usize;
+
+    /// Get the position of a coordinate in the order produced by `extent_iterator`,
+    /// or `None` if the coordinate lies outside of the extent
+    fn extent_index(&self, min: Self, max: Self) -> Option<usize>;
+
+    /// Check if a coordinate lies on the boundary of an extent, i.e. whether removing
+    /// it might shrink the bounding box of a map
+    fn on_boundary(&self, min: Self, max: Self) -> bool;
+
+    /// Get the offsets to all coordinates in a neighborhood, excluding the zero offset
+    fn neighbor_offsets(neighborhood: Neighborhood) -> Vec<Self::Offset>;
+
+    /// Translate a coordinate by an offset, or `None` if the result cannot be represented
+    fn offset(&self, by: &Self::Offset) -> Option<Self>;
+
+    /// Translate a coordinate by an offset on a map whose axes have a topology relative
+    /// to its extent. Returns `None` if the result lies outside of a bounded axis.
+    fn offset_within(
+        &self,
+        by: &Self::Offset,
+        bounds: &Bounds<Self>,
+        topology: &[Topology],
+    ) -> Option<Self>;
+
+    /// Move a coordinate into the extent along wrapping axes, or get `None` if it lies
+    /// outside of a bounded axis
+    fn wrap(&self, bounds: &Bounds<Self>, topology: &[Topology]) -> Option<Self>;
+
+    /// Get the number of coordinates in a chunk spanning `size` coordinates along every axis
+    fn chunk_len(size: usize) -> usize;
+
+    /// Split a coordinate into the coordinate of the chunk containing it and its position
+    /// within the chunk, or `None` if the chunk cannot be represented
+    fn chunk_split(&self, size: usize) -> Option<(Self, usize)>;
+
+    /// Get the coordinate at a position within a chunk, reversing `chunk_split`
+    fn chunk_join(chunk: Self, index: usize, size: usize) -> Self;
+
+    /// Get all coordinates in the neighborhood of a coordinate
+    fn neighbors(&self, neighborhood: Neighborhood) -> Vec<Self> {
+        Self::neighbor_offsets(neighborhood)
+            .iter()
+            .filter_map(|ofs| self.offset(ofs))
+            .collect()
+    }
 }
 
-/// A tile-based map that is generic over coordinates and tiles stored within
-#[derive(Default, Debug, Clone, Eq, PartialEq)]
-pub struct Map<C: MapCoordinate, T> {
-    pub data: HashMap<C, T>,
-    pub fixed_extent: Option<(C, C)>,
+/// Number of coordinates in an extent of an array coordinate
+pub(crate) fn array_extent_len<I: IntCoord>(min: &[I], max: &[I]) -> usize {
+    min.iter()
+        .zip(max.iter())
+        .map(|(a, b)| (*b - *a).to_usize().map_or(0, |d| d + 1))
+        .product()
 }
 
-impl<C: MapCoordinate, T> Map<C, T> {
-    pub fn new() -> Self {
-        Map {
-            data: HashMap::new(),
-            fixed_extent: None,
+/// Position of an array coordinate within an extent, with the first axis varying fastest
+pub(crate) fn array_extent_index<I: IntCoord>(coord: &[I], min: &[I], max: &[I]) -> Option<usize> {
+    let mut index = 0;
+    let mut stride = 1;
+    for ((c, a), b) in coord.iter().zip(min.iter()).zip(max.iter()) {
+        if c < a || c > b {
+            return None;
         }
+
+        index += (*c - *a).to_usize()? * stride;
+        stride *= (*b - *a).to_usize()? + 1;
     }
+    Some(index)
+}
 
-    /// Get the tile at a coordinate
-    pub fn get(&self, coord: &C) -> Option<&T> {
-        self.data.get(coord)
+/// Whether an array coordinate is at the minimum or maximum of an extent along any axis
+pub(crate) fn array_on_boundary<I: IntCoord>(coord: &[I], min: &[I], max: &[I]) -> bool {
+    coord
+        .iter()
+        .zip(min.iter())
+        .zip(max.iter())
+        .any(|((c, a), b)| c == a || c == b)
+}
+
+/// An inclusive, axis-aligned box of coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Bounds<C> {
+    pub min: C,
+    pub max: C,
+}
+
+impl<C: MapCoordinate> Bounds<C> {
+    pub fn new(min: C, max: C) -> Self {
+        Bounds { min, max }
     }
 
-    /// Get a mutable reference to a tile at a coordinate
-    pub fn get_mut(&mut self, coord: &C) -> Option<&mut T> {
-        self.data.get_mut(coord)
+    /// Get the smallest bounds containing all coordinates, or `None` if there are none
+    pub fn from_coords(mut coords: impl Iterator<Item = C>) -> Option<Self> {
+        let first = coords.next()?;
+        let (min, max) = C::get_extent(std::iter::once(first).chain(coords));
+        Some(Bounds { min, max })
     }
 
-    /// Set the tile at a coordinate
-    pub fn set(&mut self, coord: C, value: T) {
-        self.data.insert(coord, value);
+    /// Check if a coordinate lies within the bounds
+    pub fn contains(&self, coord: &C) -> bool {
+        C::elementwise_min(*coord, self.min) == self.min
+            && C::elementwise_max(*coord, self.max) == self.max
     }
 
-    /// Clear a coordinate from tiles
-    pub fn remove(&mut self, coord: &C) {
-        self.data.remove(coord);
+    /// Grow the bounds to contain a coordinate
+    pub fn include(&mut self, coord: C) {
+        self.min = C::elementwise_min(self.min, coord);
+        self.max = C::elementwise_max(self.max, coord);
     }
 
-    /// Get the maximum dimension for all defined tiles
-    pub fn get_extent(&self) -> (C, C) {
-        if let Some(e) = &self.fixed_extent {
-            e.clone()
+    /// Get the smallest bounds containing both bounds
+    pub fn union(&self, other: &Self) -> Self {
+        Bounds {
+            min: C::elementwise_min(self.min, other.min),
+            max: C::elementwise_max(self.max, other.max),
+        }
+    }
+
+    /// Get the coordinates contained in both bounds, or `None` if they do not overlap
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let min = C::elementwise_max(self.min, other.min);
+        let max = C::elementwise_min(self.max, other.max);
+
+        if C::elementwise_min(min, max) == min {
+            Some(Bounds { min, max })
         } else {
-            C::get_extent(self.data.keys().cloned())
+            None
         }
     }
 
-    /// Find all coordinates that match a predicate
-    pub fn find_all_where<P: Fn(&C, &T) -> bool>(&self, predicate: P) -> Vec<C> {
-        let mut out: Vec<C> = Vec::new();
-        for (coord, tile) in self.data.iter() {
-            if predicate(coord, tile) {
-                out.push(coord.clone());
+    /// Grow the bounds by `n` steps of the Moore neighborhood in every direction.
+    /// Coordinates that cannot be represented are left at the edge of their range.
+    pub fn expand(&self, n: usize) -> Self {
+        let offsets = C::neighbor_offsets(Neighborhood::Moore(1));
+        let mut out = *self;
+        for _ in 0..n {
+            let Bounds { min, max } = out;
+            for ofs in &offsets {
+                if let Some(c) = min.offset(ofs) {
+                    out.min = C::elementwise_min(out.min, c);
+                }
+                if let Some(c) = max.offset(ofs) {
+                    out.max = C::elementwise_max(out.max, c);
+                }
             }
         }
         out
     }
 
-    /// Find a coordinate that matches a predicate
-    pub fn find_one_where<P: Fn(&C, &T) -> bool>(&self, predicate: P) -> Option<C> {
-        for (coord, tile) in self.data.iter() {
-            if predicate(coord, tile) {
-                return Some(coord.clone());
+    /// Shrink the bounds by `n` steps of the Moore neighborhood from every direction,
+    /// or get `None` if nothing is left
+    pub fn shrink(&self, n: usize) -> Option<Self> {
+        let offsets = C::neighbor_offsets(Neighborhood::Moore(1));
+        let mut out = *self;
+        for _ in 0..n {
+            let Bounds { min, max } = out;
+            for ofs in &offsets {
+                if let Some(c) = min.offset(ofs) {
+                    out.min = C::elementwise_max(out.min, c);
+                }
+                if let Some(c) = max.offset(ofs) {
+                    out.max = C::elementwise_min(out.max, c);
+                }
             }
         }
-        None
+
+        if C::elementwise_min(out.min, out.max) == out.min {
+            Some(out)
+        } else {
+            None
+        }
     }
-}
 
-impl<C: MapCoordinate, T: Eq> Map<C, T> {
-    /// Find all coordinates that contain a tile
-    pub fn find_all(&self, pattern: &T) -> Vec<C> {
-        self.find_all_where(|_, t| t == pattern)
+    /// Get the number of coordinates within the bounds
+    pub fn count(&self) -> usize {
+        C::extent_len(self.min, self.max)
     }
 
-    /// Find one coordinate that contains a tile
-    pub fn find_one(&self, pattern: &T) -> Option<C> {
-        self.find_one_where(|_, t| t == pattern)
+    /// Get the position of a coordinate in the order produced by `iter`
+    pub fn index_of(&self, coord: &C) -> Option<usize> {
+        coord.extent_index(self.min, self.max)
+    }
+
+    /// Iterate over all coordinates within the bounds
+    pub fn iter(&self) -> C::ExtentIter {
+        C::extent_iterator(self.min, self.max)
     }
 }
 
-////// Code for 2D maps
+impl<C: MapCoordinate> IntoIterator for Bounds<C> {
+    type Item = C;
+    type IntoIter = C::ExtentIter;
 
-impl<I> MapCoordinate for [I; 2]
-where
-    I: IntCoord,
-{
-    type ExtentIter = Extent2DIterator<I>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
 
-    fn elementwise_min(a: Self, b: Self) -> Self {
-        [std::cmp::min(a[0], b[0]), std::cmp::min(a[1], b[1])]
+impl<C> From<(C, C)> for Bounds<C> {
+    fn from((min, max): (C, C)) -> Self {
+        Bounds { min, max }
     }
+}
 
-    fn elementwise_max(a: Self, b: Self) -> Self {
-        [std::cmp::max(a[0], b[0]), std::cmp::max(a[1], b[1])]
+impl<C> From<Bounds<C>> for (C, C) {
+    fn from(bounds: Bounds<C>) -> Self {
+        (bounds.min, bounds.max)
     }
+}
 
-    fn extent_iterator(min: Self, max: Self) -> Self::ExtentIter {
-        Extent2DIterator {
-            min,
-            max,
-            current: Some(min.clone()),
+/// Split an array coordinate into chunk coordinate and position within the chunk,
+/// with the first axis varying fastest
+pub(crate) fn array_chunk_split<I: IntCoord>(
+    coord: &[I],
+    size: usize,
+    chunk: &mut [I],
+) -> Option<usize> {
+    let s = I::from_usize(size)?;
+
+    let mut index = 0;
+    let mut stride = 1;
+    for (c, out) in coord.iter().zip(chunk.iter_mut()) {
+        let (mut q, mut r) = (*c / s, *c % s);
+        if r < I::zero() {
+            q = q - I::one();
+            r = r + s;
         }
+        q.checked_mul(&s)?;
+
+        *out = q;
+        index += r.to_usize()? * stride;
+        stride *= size;
     }
+    Some(index)
 }
 
-pub struct Extent2DIterator<I>
-where
-    I: IntCoord,
-{
-    min: [I; 2],
-    max: [I; 2],
-    current: Option<[I; 2]>,
+/// Get an array coordinate from a chunk coordinate and position within the chunk
+pub(crate) fn array_chunk_join<I: IntCoord>(chunk: &[I], index: usize, size: usize, out: &mut [I]) {
+    let s = I::from_usize(size).expect("Invalid chunk size");
+
+    let mut index = index;
+    for (c, o) in chunk.iter().zip(out.iter_mut()) {
+        *o = *c * s + I::from_usize(index % size).expect("Invalid chunk size");
+        index /= size;
+    }
 }
 
-impl<I> Iterator for Extent2DIterator<I>
-where
-    I: IntCoord,
-{
-    type Item = [I; 2];
+/// Tiles stored in a flat array that spans a fixed extent
+#[derive(Debug, Clone)]
+struct DenseGrid<C, T> {
+    bounds: Bounds<C>,
+    cells: Vec<Option<T>>,
+    len: usize,
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some(c) = self.current {
-            let mut next = c.clone();
+impl<C: MapCoordinate, T> DenseGrid<C, T> {
+    fn new(bounds: Bounds<C>) -> Self {
+        let cells = std::iter::repeat_with(|| None)
+            .take(bounds.count())
+            .collect();
 
-            next[0] = next[0] + I::one();
-            if next[0] > self.max[0] {
-                next[0] = self.min[0];
-                next[1] = next[1] + I::one();
-            }
+        DenseGrid {
+            bounds,
+            cells,
+            len: 0,
+        }
+    }
 
-            if next[1] > self.max[1] {
-                self.current = None
-            } else {
-                self.current = Some(next)
+    fn from_sparse(data: HashMap<C, T>, bounds: Bounds<C>) -> Self {
+        let mut grid = DenseGrid::new(bounds);
+        for (coord, tile) in data {
+            if grid.set(coord, tile).is_err() {
+                panic!("Coordinate {:?} outside of dense extent", coord);
             }
-
-            Some(c)
-        } else {
-            None
         }
+        grid
     }
-}
 
-impl<T, I> Map<[I; 2], T>
-where
-    T: ParseMapTile,
-    I: IntCoord,
-{
-    pub fn read<R: std::io::Read>(reader: &mut R) -> MapResult<Self> {
-        let mut data: HashMap<[I; 2], T> = HashMap::new();
+    fn get(&self, coord: &C) -> Option<&T> {
+        let i = self.bounds.index_of(coord)?;
+        self.cells[i].as_ref()
+    }
 
-        let buf_reader = BufReader::new(reader);
-        for (i, line) in buf_reader.lines().enumerate() {
-            for (j, c) in line.context(Io)?.chars().enumerate() {
-                if let Some(t) = T::from_char(c) {
-                    if let (Some(i), Some(j)) = (I::from_usize(i), I::from_usize(j)) {
-                        data.insert([i, j], t);
-                    }
+    fn get_mut(&mut self, coord: &C) -> Option<&mut T> {
+        let i = self.bounds.index_of(coord)?;
+        self.cells[i].as_mut()
+    }
+
+    /// Set a tile, handing it back if the coordinate lies outside of the grid
+    fn set(&mut self, coord: C, value: T) -> Result<(), T> {
+        match self.bounds.index_of(&coord) {
+            Some(i) => {
+                if self.cells[i].replace(value).is_none() {
+                    self.len += 1;
                 }
+                Ok(())
             }
+            None => Err(value),
         }
+    }
 
-        Ok(Map {
-            data,
-            fixed_extent: None,
-        })
+    fn remove(&mut self, coord: &C) {
+        if let Some(i) = self.bounds.index_of(coord) {
+            if self.cells[i].take().is_some() {
+                self.len -= 1;
+            }
+        }
+    }
+
+    fn into_sparse(self) -> HashMap<C, T> {
+        self.bounds
+            .iter()
+            .zip(self.cells)
+            .filter_map(|(coord, tile)| tile.map(|t| (coord, t)))
+            .collect()
     }
 }
 
-impl<T, I> Map<[I; 2], T>
-where
-    T: MapTile,
-    I: IntCoord,
-{
-    pub fn rotate_right(&self) -> Self {
-        let (min, max) = self.get_extent();
-        assert_eq!(min[0], I::zero());
-        assert_eq!(min[1], I::zero());
+/// A fixed-size block of tiles in chunked storage
+#[derive(Debug, Clone)]
+struct Chunk<T> {
+    cells: Vec<Option<T>>,
+    len: usize,
+}
 
-        let mut out = Map::new();
+/// Tiles stored in fixed-size chunks that are shared between clones until modified
+#[derive(Debug, Clone)]
+struct ChunkGrid<C, T> {
+    size: usize,
+    chunks: HashMap<C, Arc<Chunk<T>>>,
+    len: usize,
 
-        //  j 01234    0123
-        // i
-        // 0  abcde    kfa
-        // 1  fghIj    Lgb
-        // 2  kLmno    mhc
-        // 3           nId
-        // 4           oje
+    /// Clones a chunk before it is modified while shared. Stored so that only creating
+    /// chunked storage requires `T: Clone`.
+    clone_chunk: fn(&Chunk<T>) -> Chunk<T>,
+}
+
+/// Get mutable access to a chunk, cloning it first if it is shared
+fn unique_chunk<T>(
+    chunk: &mut Arc<Chunk<T>>,
+    clone_chunk: fn(&Chunk<T>) -> Chunk<T>,
+) -> &mut Chunk<T> {
+    if Arc::get_mut(chunk).is_none() {
+        *chunk = Arc::new(clone_chunk(chunk));
+    }
+    Arc::get_mut(chunk).expect("Chunk was just made unique")
+}
 
-        for ([i, j], tile) in self.data.iter() {
-            out.set([*j, max[0] - *i], tile.clone());
+impl<C: MapCoordinate, T: Clone> ChunkGrid<C, T> {
+    /// Move sparse tiles into chunks, handing them back if some coordinate cannot be
+    /// chunked
+    fn from_sparse(data: HashMap<C, T>, size: usize) -> Result<Self, HashMap<C, T>> {
+        if data.keys().any(|c| c.chunk_split(size).is_none()) {
+            return Err(data);
         }
 
-        out
+        let mut grid = ChunkGrid {
+            size,
+            chunks: HashMap::new(),
+            len: 0,
+            clone_chunk: Chunk::clone,
+        };
+        for (coord, tile) in data {
+            if grid.set(coord, tile).is_err() {
+                unreachable!("Coordinate was checked to be chunkable");
+            }
+        }
+        Ok(grid)
     }
+}
 
-    pub fn rotate_left(&self) -> Self {
-        let (min, max) = self.get_extent();
-        assert_eq!(min[0], I::zero());
-        assert_eq!(min[1], I::zero());
+impl<C: MapCoordinate, T> ChunkGrid<C, T> {
+    fn get(&self, coord: &C) -> Option<&T> {
+        let (key, i) = coord.chunk_split(self.size)?;
+        self.chunks.get(&key)?.cells[i].as_ref()
+    }
 
-        let mut out = Map::new();
+    fn get_mut(&mut self, coord: &C) -> Option<&mut T> {
+        let (key, i) = coord.chunk_split(self.size)?;
+        let chunk = self.chunks.get_mut(&key)?;
+        chunk.cells[i].as_ref()?;
+        unique_chunk(chunk, self.clone_chunk).cells[i].as_mut()
+    }
 
-        //  j 01234    0123
-        // i
-        // 0  abcde    ejo
-        // 1  fghIj    dIn
-        // 2  kLmno    chm
-        // 3           bgL
-        // 4           afk
+    /// Set a tile, handing it back if the coordinate cannot be chunked
+    fn set(&mut self, coord: C, value: T) -> Result<(), T> {
+        let (key, i) = match coord.chunk_split(self.size) {
+            Some(split) => split,
+            None => return Err(value),
+        };
 
-        for ([i, j], tile) in self.data.iter() {
-            out.set([max[1] - *j, *i], tile.clone());
-        }
+        let chunk_len = C::chunk_len(self.size);
+        let chunk = self.chunks.entry(key).or_insert_with(|| {
+            Arc::new(Chunk {
+                cells: std::iter::repeat_with(|| None).take(chunk_len).collect(),
+                len: 0,
+            })
+        });
 
-        out
+        let chunk = unique_chunk(chunk, self.clone_chunk);
+        if chunk.cells[i].replace(value).is_none() {
+            chunk.len += 1;
+            self.len += 1;
+        }
+        Ok(())
     }
 
-    pub fn flip(&self, axis: usize) -> Self {
-        let (_min, max) = self.get_extent();
+    fn remove(&mut self, coord: &C) {
+        let (key, i) = match coord.chunk_split(self.size) {
+            Some(split) => split,
+            None => return,
+        };
 
-        let mut out = Map::new();
-        for (pos, tile) in self.data.iter() {
-            let mut pos = pos.clone();
-            pos[axis] = max[axis] - pos[axis];
+        if let Some(chunk) = self.chunks.get_mut(&key) {
+            if chunk.cells[i].is_none() {
+                return;
+            }
 
-            out.set(pos, tile.clone());
-        }
+            let chunk = unique_chunk(chunk, self.clone_chunk);
+            chunk.cells[i] = None;
+            chunk.len -= 1;
+            self.len -= 1;
 
-        out
+            if chunk.len == 0 {
+                self.chunks.remove(&key);
+            }
+        }
     }
 
-    pub fn to_vecs(&self) -> Vec<Vec<Option<T>>> {
-        let (min, max) = self.get_extent();
-
-        num::iter::range_inclusive(min[0], max[0])
-            .map(|i| {
-                num::iter::range_inclusive(min[1], max[1])
-                    .map(|j| self.data.get(&[i, j]).cloned())
-                    .collect()
+    fn into_sparse(self) -> HashMap<C, T> {
+        let size = self.size;
+        let clone_chunk = self.clone_chunk;
+
+        self.chunks
+            .into_iter()
+            .flat_map(|(key, chunk)| {
+                let chunk = Arc::try_unwrap(chunk).unwrap_or_else(|shared| clone_chunk(&shared));
+                chunk
+                    .cells
+                    .into_iter()
+                    .enumerate()
+                    .filter_map(move |(i, t)| t.map(|t| (C::chunk_join(key, i, size), t)))
             })
             .collect()
     }
 }
 
-impl<T, I> std::fmt::Display for Map<[I; 2], T>
-where
-    T: MapTile,
-    I: IntCoord,
-{
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
-        if self.data.is_empty() {
-            return Ok(());
-        }
+/// Backing storage of a map
+#[derive(Debug, Clone)]
+enum Storage<C: MapCoordinate, T> {
+    /// Only defined tiles are stored, keyed by their coordinate
+    Sparse(HashMap<C, T>),
 
-        let (min, max) = self.get_extent();
+    /// All coordinates in an extent are stored, whether a tile is defined or not
+    Dense(DenseGrid<C, T>),
 
-        for i in num::iter::range_inclusive(min[0], max[0]) {
-            for j in num::iter::range_inclusive(min[1], max[1]) {
-                match self.data.get(&[i, j]) {
-                    Some(t) => t.fmt(f),
-                    None => write!(f, " "),
-                }?;
-            }
-            write!(f, "\n")?;
-        }
+    /// Tiles are stored in fixed-size chunks keyed by chunk coordinate
+    Chunked(ChunkGrid<C, T>),
+}
 
-        Ok(())
+impl<C: MapCoordinate, T> Default for Storage<C, T> {
+    fn default() -> Self {
+        Storage::Sparse(HashMap::new())
     }
 }
 
-impl<T, I> std::str::FromStr for Map<[I; 2], T>
-where
-    T: ParseMapTile,
-    I: IntCoord,
-{
-    type Err = MapError;
+/// Iterator over the coordinates and tiles of a map
+pub struct Iter<'a, C: MapCoordinate, T> {
+    inner: IterInner<'a, C, T>,
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Map::read(&mut s.as_bytes())
-    }
+enum IterInner<'a, C: MapCoordinate, T> {
+    Sparse(hash_map::Iter<'a, C, T>),
+    Dense(std::iter::Zip<C::ExtentIter, std::slice::Iter<'a, Option<T>>>),
+    Chunked(ChunkIter<'a, C, T>),
 }
 
-////// Code for 3D maps
+struct ChunkIter<'a, C: MapCoordinate, T> {
+    size: usize,
+    chunks: hash_map::Iter<'a, C, Arc<Chunk<T>>>,
+    current: Option<(C, std::iter::Enumerate<std::slice::Iter<'a, Option<T>>>)>,
+}
 
-impl<I> MapCoordinate for [I; 3]
-where
-    I: IntCoord,
-{
-    type ExtentIter = Extent3DIterator<I>;
+impl<'a, C: MapCoordinate, T> Iterator for ChunkIter<'a, C, T> {
+    type Item = (C, &'a T);
 
-    fn elementwise_min(a: Self, b: Self) -> Self {
-        [
-            std::cmp::min(a[0], b[0]),
-            std::cmp::min(a[1], b[1]),
-            std::cmp::min(a[2], b[2]),
-        ]
-    }
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((key, cells)) = &mut self.current {
+                if let Some((i, t)) = cells.find_map(|(i, t)| t.as_ref().map(|t| (i, t))) {
+                    return Some((C::chunk_join(*key, i, self.size), t));
+                }
+            }
 
-    fn elementwise_max(a: Self, b: Self) -> Self {
-        [
-            std::cmp::max(a[0], b[0]),
-            std::cmp::max(a[1], b[1]),
-            std::cmp::max(a[2], b[2]),
-        ]
+            let (key, chunk) = self.chunks.next()?;
+            self.current = Some((*key, chunk.cells.iter().enumerate()));
+        }
     }
+}
 
-    fn extent_iterator(min: Self, max: Self) -> Self::ExtentIter {
-        Extent3DIterator {
-            min,
-            max,
-            current: Some(min.clone()),
+impl<'a, C: MapCoordinate, T> Iterator for Iter<'a, C, T> {
+    type Item = (C, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.inner {
+            IterInner::Sparse(it) => it.next().map(|(c, t)| (*c, t)),
+            IterInner::Dense(it) => it.find_map(|(c, t)| t.as_ref().map(|t| (c, t))),
+            IterInner::Chunked(it) => it.next(),
         }
     }
 }
 
-pub struct Extent3DIterator<I>
-where
-    I: IntCoord,
-{
-    min: [I; 3],
-    max: [I; 3],
-    current: Option<[I; 3]>,
+/// Iterator over the coordinates and mutable tiles of a map
+pub struct IterMut<'a, C: MapCoordinate, T> {
+    inner: IterMutInner<'a, C, T>,
 }
 
-impl<I> Iterator for Extent3DIterator<I>
-where
-    I: IntCoord,
-{
-    type Item = [I; 3];
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some(c) = self.current {
-            let mut next = c.clone();
+enum IterMutInner<'a, C: MapCoordinate, T> {
+    Sparse(hash_map::IterMut<'a, C, T>),
+    Dense(std::iter::Zip<C::ExtentIter, std::slice::IterMut<'a, Option<T>>>),
+    Chunked(ChunkIterMut<'a, C, T>),
+}
 
-            next[0] = next[0] + I::one();
-            if next[0] > self.max[0] {
-                next[0] = self.min[0];
-                next[1] = next[1] + I::one();
-            }
+struct ChunkIterMut<'a, C: MapCoordinate, T> {
+    size: usize,
+    clone_chunk: fn(&Chunk<T>) -> Chunk<T>,
+    chunks: hash_map::IterMut<'a, C, Arc<Chunk<T>>>,
+    current: Option<(C, std::iter::Enumerate<std::slice::IterMut<'a, Option<T>>>)>,
+}
 
-            if next[1] > self.max[1] {
-                next[1] = self.min[1];
-                next[2] = next[2] + I::one();
-            }
+impl<'a, C: MapCoordinate, T> Iterator for ChunkIterMut<'a, C, T> {
+    type Item = (C, &'a mut T);
 
-            if next[2] > self.max[2] {
-                self.current = None
-            } else {
-                self.current = Some(next)
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((key, cells)) = &mut self.current {
+                if let Some((i, t)) = cells.find_map(|(i, t)| t.as_mut().map(|t| (i, t))) {
+                    return Some((C::chunk_join(*key, i, self.size), t));
+                }
             }
 
-            Some(c)
-        } else {
-            None
+            let (key, chunk) = self.chunks.next()?;
+            let chunk = unique_chunk(chunk, self.clone_chunk);
+            self.current = Some((*key, chunk.cells.iter_mut().enumerate()));
         }
     }
 }
 
-impl<T, I> Map<[I; 3], T>
-where
-    T: MapTile,
-    I: IntCoord,
-{
-    /// Convert a 2D map to a single-layered 3D map
-    pub fn from_2d(map: &Map<[I; 2], T>) -> Self {
-        let data: HashMap<[I; 3], T> = map
-            .data
-            .iter()
-            .map(|(key, tile)| {
-                let key = [I::zero(), key[0], key[1]];
-                (key, tile.clone())
-            })
-            .collect();
+impl<'a, C: MapCoordinate, T> Iterator for IterMut<'a, C, T> {
+    type Item = (C, &'a mut T);
 
-        Map {
-            data,
-            fixed_extent: None,
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.inner {
+            IterMutInner::Sparse(it) => it.next().map(|(c, t)| (*c, t)),
+            IterMutInner::Dense(it) => it.find_map(|(c, t)| t.as_mut().map(|t| (c, t))),
+            IterMutInner::Chunked(it) => it.next(),
         }
     }
+}
 
-    /// Slice a 3D map into a 2D map along one dimension
-    pub fn slice(&self, i: I, axis: usize) -> Map<[I; 2], T> {
-        let (ax0, ax1) = match axis {
-            0 => (1, 2),
-            1 => (0, 2),
-            2 => (0, 1),
-            _ => panic!("Invalid axis: {}", axis),
-        };
+/// A tile-based map that is generic over coordinates and tiles stored within
+///
+/// Tiles are stored sparsely by default. Fully populated maps can switch to a dense,
+/// array-backed storage using `to_dense`, which `Map::read` does automatically.
+/// Unbounded maps can switch to chunked storage using `to_chunked`, which makes clones
+/// share all chunks until they are modified.
+#[derive(Debug, Clone)]
+pub struct Map<C: MapCoordinate, T> {
+    storage: Storage<C, T>,
 
-        let data: HashMap<[I; 2], T> = self
-            .data
+    /// Bounding box of the tiles, grown by `set` and recomputed on first use after
+    /// `remove` cleared a tile on its boundary
+    bbox: OnceLock<Option<Bounds<C>>>,
+
+    /// Coordinates touched by `set` and `remove` since the journal was last taken
+    journal: Option<HashSet<C>>,
+
+    /// Extent reported by `bounds` instead of the bounding box of the tiles
+    pub fixed_extent: Option<Bounds<C>>,
+
+    /// Neighborhood used by `neighbors` and `count_neighbors_where`
+    neighborhood: Neighborhood,
+
+    /// Offsets of `neighborhood`, computed on first use
+    neighbor_offsets: OnceLock<Vec<C::Offset>>,
+
+    /// Topology of each axis, empty if all axes are infinite
+    topology: Vec<Topology>,
+}
+
+impl<C: MapCoordinate, T> Default for Map<C, T> {
+    fn default() -> Self {
+        Map::new()
+    }
+}
+
+impl<C: MapCoordinate, T> From<HashMap<C, T>> for Map<C, T> {
+    fn from(data: HashMap<C, T>) -> Self {
+        Map {
+            bbox: OnceLock::from(Bounds::from_coords(data.keys().cloned())),
+            storage: Storage::Sparse(data),
+            journal: None,
+            fixed_extent: None,
+            neighborhood: Neighborhood::default(),
+            neighbor_offsets: OnceLock::new(),
+            topology: Vec::new(),
+        }
+    }
+}
+
+impl<C: MapCoordinate, T> Map<C, T> {
+    pub fn new() -> Self {
+        Map {
+            storage: Storage::default(),
+            bbox: OnceLock::from(None),
+            journal: None,
+            fixed_extent: None,
+            neighborhood: Neighborhood::default(),
+            neighbor_offsets: OnceLock::new(),
+            topology: Vec::new(),
+        }
+    }
+
+    /// Create an empty map with dense storage spanning an extent.
+    /// Setting tiles outside of the extent will switch the map to sparse storage.
+    pub fn new_dense(bounds: Bounds<C>) -> Self {
+        Map {
+            storage: Storage::Dense(DenseGrid::new(bounds)),
+            bbox: OnceLock::from(None),
+            journal: None,
+            fixed_extent: None,
+            neighborhood: Neighborhood::default(),
+            neighbor_offsets: OnceLock::new(),
+            topology: Vec::new(),
+        }
+    }
+
+    /// Get the tile at a coordinate
+    pub fn get(&self, coord: &C) -> Option<&T> {
+        let coord = &self.wrap(coord)?;
+        match &self.storage {
+            Storage::Sparse(data) => data.get(coord),
+            Storage::Dense(grid) => grid.get(coord),
+            Storage::Chunked(grid) => grid.get(coord),
+        }
+    }
+
+    /// Get a mutable reference to a tile at a coordinate
+    pub fn get_mut(&mut self, coord: &C) -> Option<&mut T> {
+        let coord = &self.wrap(coord)?;
+        match &mut self.storage {
+            Storage::Sparse(data) => data.get_mut(coord),
+            Storage::Dense(grid) => grid.get_mut(coord),
+            Storage::Chunked(grid) => grid.get_mut(coord),
+        }
+    }
+
+    /// Set the tile at a coordinate. Coordinates outside of a bounded axis are ignored.
+    pub fn set(&mut self, coord: C, value: T) {
+        let coord = match self.wrap(&coord) {
+            Some(coord) => coord,
+            None => return,
+        };
+
+        if let Some(journal) = &mut self.journal {
+            journal.insert(coord);
+        }
+
+        match self.bbox.get_mut() {
+            Some(Some(bbox)) => bbox.include(coord),
+            Some(bbox) => *bbox = Some(Bounds::new(coord, coord)),
+            None => {}
+        }
+
+        let value = match &mut self.storage {
+            Storage::Sparse(data) => {
+                data.insert(coord, value);
+                return;
+            }
+            Storage::Dense(grid) => match grid.set(coord, value) {
+                Ok(()) => return,
+                Err(value) => value,
+            },
+            Storage::Chunked(grid) => match grid.set(coord, value) {
+                Ok(()) => return,
+                Err(value) => value,
+            },
+        };
+
+        // coordinate is outside of the dense grid or cannot be chunked
+        self.to_sparse();
+        self.set(coord, value);
+    }
+
+    /// Clear a coordinate from tiles. Removing a tile on the boundary of the extent
+    /// only marks it for recomputation the next time it is needed.
+    pub fn remove(&mut self, coord: &C) {
+        let coord = &match self.wrap(coord) {
+            Some(coord) => coord,
+            None => return,
+        };
+        let on_boundary = match self.bbox.get() {
+            Some(Some(bbox)) => coord.on_boundary(bbox.min, bbox.max) && self.get(coord).is_some(),
+            _ => false,
+        };
+
+        if let Some(journal) = &mut self.journal {
+            journal.insert(*coord);
+        }
+
+        match &mut self.storage {
+            Storage::Sparse(data) => {
+                data.remove(coord);
+            }
+            Storage::Dense(grid) => grid.remove(coord),
+            Storage::Chunked(grid) => grid.remove(coord),
+        }
+
+        if on_boundary {
+            self.bbox = OnceLock::new();
+        }
+    }
+
+    /// Get the number of defined tiles
+    pub fn len(&self) -> usize {
+        match &self.storage {
+            Storage::Sparse(data) => data.len(),
+            Storage::Dense(grid) => grid.len,
+            Storage::Chunked(grid) => grid.len,
+        }
+    }
+
+    /// Check if the map has no defined tiles
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the coordinates and tiles of all defined tiles
+    pub fn iter(&self) -> Iter<'_, C, T> {
+        let inner = match &self.storage {
+            Storage::Sparse(data) => IterInner::Sparse(data.iter()),
+            Storage::Dense(grid) => IterInner::Dense(grid.bounds.iter().zip(grid.cells.iter())),
+            Storage::Chunked(grid) => IterInner::Chunked(ChunkIter {
+                size: grid.size,
+                chunks: grid.chunks.iter(),
+                current: None,
+            }),
+        };
+
+        Iter { inner }
+    }
+
+    /// Iterate over the coordinates and mutable tiles of all defined tiles.
+    /// Chunks shared with other maps are copied, even if no tile is modified.
+    pub fn iter_mut(&mut self) -> IterMut<'_, C, T> {
+        let inner = match &mut self.storage {
+            Storage::Sparse(data) => IterMutInner::Sparse(data.iter_mut()),
+            Storage::Dense(grid) => {
+                IterMutInner::Dense(grid.bounds.iter().zip(grid.cells.iter_mut()))
+            }
+            Storage::Chunked(grid) => IterMutInner::Chunked(ChunkIterMut {
+                size: grid.size,
+                clone_chunk: grid.clone_chunk,
+                chunks: grid.chunks.iter_mut(),
+                current: None,
+            }),
+        };
+
+        IterMut { inner }
+    }
+
+    /// Iterate over the coordinates of all defined tiles
+    pub fn keys(&self) -> impl Iterator<Item = C> + '_ {
+        self.iter().map(|(c, _)| c)
+    }
+
+    /// Iterate over all defined tiles
+    pub fn values(&self) -> impl Iterator<Item = &T> + '_ {
+        self.iter().map(|(_, t)| t)
+    }
+
+    /// Start recording the coordinates touched by `set` and `remove`.
+    /// Changes made through `get_mut` and `iter_mut` are not recorded.
+    pub fn enable_journal(&mut self) {
+        if self.journal.is_none() {
+            self.journal = Some(HashSet::new());
+        }
+    }
+
+    /// Stop recording changes and drop the journal
+    pub fn disable_journal(&mut self) {
+        self.journal = None;
+    }
+
+    /// Get the coordinates touched since the journal was enabled or last taken, and
+    /// clear it. Returns `None` if the journal is disabled.
+    pub fn take_journal(&mut self) -> Option<HashSet<C>> {
+        self.journal.as_mut().map(std::mem::take)
+    }
+
+    /// Check if the map uses dense storage
+    pub fn is_dense(&self) -> bool {
+        matches!(self.storage, Storage::Dense(_))
+    }
+
+    /// Switch to dense storage spanning the current extent
+    pub fn to_dense(&mut self) {
+        if self.is_dense() {
+            return;
+        }
+
+        self.to_sparse();
+        let bounds = self
+            .bbox()
+            .unwrap_or_else(|| Bounds::new(C::origin(), C::origin()));
+        if let Storage::Sparse(data) = std::mem::take(&mut self.storage) {
+            self.storage = Storage::Dense(DenseGrid::from_sparse(data, bounds));
+        }
+    }
+
+    /// Switch to sparse storage
+    pub fn to_sparse(&mut self) {
+        self.storage = match std::mem::take(&mut self.storage) {
+            Storage::Dense(grid) => Storage::Sparse(grid.into_sparse()),
+            Storage::Chunked(grid) => Storage::Sparse(grid.into_sparse()),
+            sparse => sparse,
+        };
+    }
+
+    /// Check if the map uses chunked storage
+    pub fn is_chunked(&self) -> bool {
+        matches!(self.storage, Storage::Chunked(_))
+    }
+
+    /// Get the extent of the map, which is `fixed_extent` if set and the bounding box
+    /// of the tiles otherwise. Empty maps span only the origin.
+    pub fn bounds(&self) -> Bounds<C> {
+        self.fixed_extent
+            .or_else(|| self.bbox())
+            .unwrap_or_else(|| Bounds::new(C::origin(), C::origin()))
+    }
+
+    /// Get the bounding box of the tiles, recomputing it if `remove` invalidated it
+    fn bbox(&self) -> Option<Bounds<C>> {
+        *self.bbox.get_or_init(|| Bounds::from_coords(self.keys()))
+    }
+
+    /// Get the extent of the map as a `(min, max)` tuple
+    pub fn get_extent(&self) -> (C, C) {
+        self.bounds().into()
+    }
+
+    /// Recompute the bounding box from the defined tiles now rather than on its next use
+    pub fn shrink_to_fit_extent(&mut self) {
+        self.bbox = OnceLock::from(Bounds::from_coords(self.keys()));
+    }
+
+    /// Get the neighborhood used by `neighbors` and `count_neighbors_where`
+    pub fn neighborhood(&self) -> Neighborhood {
+        self.neighborhood
+    }
+
+    /// Set the neighborhood used by `neighbors` and `count_neighbors_where`
+    pub fn set_neighborhood(&mut self, neighborhood: Neighborhood) {
+        if neighborhood != self.neighborhood {
+            self.neighborhood = neighborhood;
+            self.neighbor_offsets = OnceLock::new();
+        }
+    }
+
+    /// Get the topology of each axis. Axes beyond the end of the slice are infinite.
+    pub fn topology(&self) -> &[Topology] {
+        &self.topology
+    }
+
+    /// Set the topology of each axis, which is honored by tile access, neighbor queries
+    /// and ray casts. Bounded and wrapping axes are relative to the extent of the map,
+    /// so `fixed_extent` is set to the current extent if it is not set yet.
+    pub fn set_topology(&mut self, topology: &[Topology]) {
+        if topology.iter().all(|t| *t == Topology::Infinite) {
+            self.topology.clear();
+            return;
+        }
+
+        if self.fixed_extent.is_none() {
+            self.fixed_extent = Some(self.bounds());
+        }
+        self.topology = topology.to_vec();
+    }
+
+    /// Apply the topology to a coordinate
+    pub(crate) fn wrap(&self, coord: &C) -> Option<C> {
+        if self.topology.is_empty() {
+            Some(*coord)
+        } else {
+            coord.wrap(&self.bounds(), &self.topology)
+        }
+    }
+
+    /// Translate a coordinate by an offset, honoring the topology of the map.
+    /// Returns `None` if the result lies outside of a bounded axis.
+    pub fn offset(&self, coord: &C, by: &C::Offset) -> Option<C> {
+        if self.topology.is_empty() {
+            coord.offset(by)
+        } else {
+            coord.offset_within(by, &self.bounds(), &self.topology)
+        }
+    }
+
+    /// Get the coordinates and tiles of all defined tiles in the neighborhood of a coordinate
+    pub fn neighbors(&self, coord: &C) -> Vec<(C, &T)> {
+        self.neighbor_offsets
+            .get_or_init(|| C::neighbor_offsets(self.neighborhood))
+            .iter()
+            .filter_map(|ofs| {
+                let n = self.offset(coord, ofs)?;
+                self.get(&n).map(|t| (n, t))
+            })
+            .collect()
+    }
+
+    /// Count the defined tiles in the neighborhood of a coordinate that match a predicate
+    pub fn count_neighbors_where<P: Fn(&C, &T) -> bool>(&self, coord: &C, predicate: P) -> usize {
+        self.neighbors(coord)
+            .into_iter()
+            .filter(|(c, t)| predicate(c, t))
+            .count()
+    }
+
+    /// Walk from `origin` in steps of `direction` and get the first defined tile that
+    /// matches a predicate. The ray ends when it leaves the extent of the map, or when it
+    /// returns to `origin` along wrapping axes.
+    pub fn cast_ray<P: Fn(&C, &T) -> bool>(
+        &self,
+        origin: &C,
+        direction: &C::Offset,
+        predicate: P,
+    ) -> Option<(C, &T)> {
+        let bounds = self.bounds();
+        let origin = self.wrap(origin)?;
+        let mut pos = origin;
+
+        loop {
+            let next = self.offset(&pos, direction)?;
+            if next == pos || next == origin || !bounds.contains(&next) {
+                return None;
+            }
+            pos = next;
+
+            if let Some(t) = self.get(&pos) {
+                if predicate(&pos, t) {
+                    return Some((pos, t));
+                }
+            }
+        }
+    }
+
+    /// Cast rays from `origin` in several directions, getting the first tile matching a
+    /// predicate along each of them
+    pub fn visible_from<P: Fn(&C, &T) -> bool>(
+        &self,
+        origin: &C,
+        directions: &[C::Offset],
+        predicate: P,
+    ) -> Vec<(C, &T)> {
+        directions
+            .iter()
+            .filter_map(|dir| self.cast_ray(origin, dir, &predicate))
+            .collect()
+    }
+
+    /// Find all coordinates that match a predicate
+    pub fn find_all_where<P: Fn(&C, &T) -> bool>(&self, predicate: P) -> Vec<C> {
+        self.iter()
+            .filter(|(coord, tile)| predicate(coord, tile))
+            .map(|(coord, _)| coord)
+            .collect()
+    }
+
+    /// Find a coordinate that matches a predicate
+    pub fn find_one_where<P: Fn(&C, &T) -> bool>(&self, predicate: P) -> Option<C> {
+        self.iter()
+            .find(|(coord, tile)| predicate(coord, tile))
+            .map(|(coord, _)| coord)
+    }
+}
+
+impl<C: MapCoordinate, T: Clone> Map<C, T> {
+    /// Get a map with only the tiles within some bounds, keeping their coordinates
+    pub fn crop(&self, bounds: Bounds<C>) -> Self {
+        let mut out = Map::new();
+        out.set_neighborhood(self.neighborhood);
+
+        if bounds.count() < self.len() {
+            for coord in bounds {
+                if let Some(t) = self.get(&coord) {
+                    out.set(coord, t.clone());
+                }
+            }
+        } else {
+            for (coord, t) in self.iter().filter(|(c, _)| bounds.contains(c)) {
+                out.set(coord, t.clone());
+            }
+        }
+
+        out
+    }
+
+    /// Remove `n` layers of coordinates from every side of the extent
+    pub fn strip_border(&self, n: usize) -> Self {
+        match self.bounds().shrink(n) {
+            Some(bounds) => self.crop(bounds),
+            None => {
+                let mut out = Map::new();
+                out.set_neighborhood(self.neighborhood);
+                out
+            }
+        }
+    }
+
+    /// Copy all tiles of another map into this one, translated by an offset
+    pub fn paste(&mut self, other: &Map<C, T>, offset: &C::Offset) {
+        for (coord, t) in other.iter() {
+            let target = coord
+                .offset(offset)
+                .unwrap_or_else(|| panic!("Cannot paste {:?} at offset {:?}", coord, offset));
+            self.set(target, t.clone());
+        }
+    }
+
+    /// Create an empty map with chunked storage, using chunks that span `size`
+    /// coordinates along every axis
+    pub fn new_chunked(size: usize) -> Self {
+        let mut map = Map::new();
+        map.to_chunked(size);
+        map
+    }
+
+    /// Switch to chunked storage, using chunks that span `size` coordinates along every
+    /// axis. Clones of a chunked map share chunks until they are modified, which makes
+    /// snapshots cheap. Stays sparse if some coordinate cannot be chunked.
+    pub fn to_chunked(&mut self, size: usize) {
+        assert!(size > 0, "Chunk size must be positive");
+
+        if let Storage::Chunked(grid) = &self.storage {
+            if grid.size == size {
+                return;
+            }
+        }
+
+        self.to_sparse();
+        if let Storage::Sparse(data) = std::mem::take(&mut self.storage) {
+            self.storage = match ChunkGrid::from_sparse(data, size) {
+                Ok(grid) => Storage::Chunked(grid),
+                Err(data) => Storage::Sparse(data),
+            };
+        }
+    }
+}
+
+impl<C: MapCoordinate, T: std::hash::Hash> Map<C, T> {
+    /// Hash the defined tiles of a map, independent of storage and iteration order
+    pub fn state_hash(&self) -> u64 {
+        use std::hash::Hasher;
+
+        self.iter()
+            .map(|(coord, tile)| {
+                let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                coord.hash(&mut hasher);
+                tile.hash(&mut hasher);
+                hasher.finish()
+            })
+            .fold(0, u64::wrapping_add)
+    }
+}
+
+impl<C: MapCoordinate, T> std::ops::Index<&C> for Map<C, T> {
+    type Output = T;
+
+    fn index(&self, coord: &C) -> &T {
+        self.get(coord)
+            .unwrap_or_else(|| panic!("No tile at {:?}", coord))
+    }
+}
+
+impl<C: MapCoordinate, T: PartialEq> PartialEq for Map<C, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.fixed_extent == other.fixed_extent
+            && self.topology == other.topology
+            && self.len() == other.len()
+            && self
+                .iter()
+                .all(|(coord, tile)| other.get(&coord) == Some(tile))
+    }
+}
+
+impl<C: MapCoordinate, T: Eq> Eq for Map<C, T> {}
+
+/// Coordinates that differ between two maps
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapDiff<C> {
+    /// Tiles that are only defined in the new map
+    pub added: Vec<C>,
+
+    /// Tiles that are only defined in the old map
+    pub removed: Vec<C>,
+
+    /// Tiles that are defined in both maps but differ
+    pub changed: Vec<C>,
+}
+
+impl<C> Default for MapDiff<C> {
+    fn default() -> Self {
+        MapDiff {
+            added: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+        }
+    }
+}
+
+impl<C> MapDiff<C> {
+    /// Get the total number of differing coordinates
+    pub fn len(&self) -> usize {
+        self.added.len() + self.removed.len() + self.changed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<C: MapCoordinate, T: PartialEq> Map<C, T> {
+    /// Get the differences needed to turn this map into another one
+    pub fn diff(&self, other: &Self) -> MapDiff<C> {
+        let mut diff = MapDiff::default();
+
+        for (coord, tile) in self.iter() {
+            match other.get(&coord) {
+                None => diff.removed.push(coord),
+                Some(t) if t != tile => diff.changed.push(coord),
+                Some(_) => {}
+            }
+        }
+
+        diff.added = other
+            .keys()
+            .filter(|coord| self.get(coord).is_none())
+            .collect();
+
+        diff
+    }
+}
+
+impl<C: MapCoordinate, T: Eq> Map<C, T> {
+    /// Find all coordinates that contain a tile
+    pub fn find_all(&self, pattern: &T) -> Vec<C> {
+        self.find_all_where(|_, t| t == pattern)
+    }
+
+    /// Find one coordinate that contains a tile
+    pub fn find_one(&self, pattern: &T) -> Option<C> {
+        self.find_one_where(|_, t| t == pattern)
+    }
+}
+
+////// Code for N-dimensional maps
+
+impl<I, const N: usize> MapCoordinate for [I; N]
+where
+    I: IntCoord,
+{
+    type ExtentIter = ExtentIterator<I, N>;
+    type Offset = [i64; N];
+
+    fn origin() -> Self {
+        [I::zero(); N]
+    }
+
+    fn elementwise_min(mut a: Self, b: Self) -> Self {
+        for (a, b) in a.iter_mut().zip(b.iter()) {
+            *a = std::cmp::min(*a, *b);
+        }
+        a
+    }
+
+    fn elementwise_max(mut a: Self, b: Self) -> Self {
+        for (a, b) in a.iter_mut().zip(b.iter()) {
+            *a = std::cmp::max(*a, *b);
+        }
+        a
+    }
+
+    fn extent_iterator(min: Self, max: Self) -> Self::ExtentIter {
+        ExtentIterator {
+            min,
+            max,
+            current: Some(min),
+        }
+    }
+
+    fn extent_len(min: Self, max: Self) -> usize {
+        array_extent_len(&min, &max)
+    }
+
+    fn extent_index(&self, min: Self, max: Self) -> Option<usize> {
+        array_extent_index(self, &min, &max)
+    }
+
+    fn on_boundary(&self, min: Self, max: Self) -> bool {
+        array_on_boundary(self, &min, &max)
+    }
+
+    fn neighbor_offsets(neighborhood: Neighborhood) -> Vec<Self::Offset> {
+        neighborhood_offsets(neighborhood)
+    }
+
+    fn offset(&self, by: &Self::Offset) -> Option<Self> {
+        let mut out = *self;
+        array_offset(self, by, &mut out)?;
+        Some(out)
+    }
+
+    fn offset_within(
+        &self,
+        by: &Self::Offset,
+        bounds: &Bounds<Self>,
+        topology: &[Topology],
+    ) -> Option<Self> {
+        let mut out = *self;
+        array_offset_within(self, by, (&bounds.min, &bounds.max), topology, &mut out)?;
+        Some(out)
+    }
+
+    fn wrap(&self, bounds: &Bounds<Self>, topology: &[Topology]) -> Option<Self> {
+        self.offset_within(&[0; N], bounds, topology)
+    }
+
+    fn chunk_len(size: usize) -> usize {
+        size.pow(N as u32)
+    }
+
+    fn chunk_split(&self, size: usize) -> Option<(Self, usize)> {
+        let mut chunk = *self;
+        let index = array_chunk_split(self, size, &mut chunk)?;
+        Some((chunk, index))
+    }
+
+    fn chunk_join(chunk: Self, index: usize, size: usize) -> Self {
+        let mut out = chunk;
+        array_chunk_join(&chunk, index, size, &mut out);
+        out
+    }
+}
+
+/// Iterator over all coordinates within an extent, with the first axis varying fastest
+pub struct ExtentIterator<I, const N: usize>
+where
+    I: IntCoord,
+{
+    min: [I; N],
+    max: [I; N],
+    current: Option<[I; N]>,
+}
+
+impl<I, const N: usize> Iterator for ExtentIterator<I, N>
+where
+    I: IntCoord,
+{
+    type Item = [I; N];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let c = self.current?;
+
+        let mut next = c;
+        self.current = None;
+        for axis in 0..N {
+            if next[axis] < self.max[axis] {
+                next[axis] = next[axis] + I::one();
+                self.current = Some(next);
+                break;
+            }
+            next[axis] = self.min[axis];
+        }
+
+        Some(c)
+    }
+}
+
+impl<T, I, const N: usize> Map<[I; N], T>
+where
+    T: MapTile,
+    I: IntCoord,
+{
+    /// Embed the map into a higher-dimensional map. The new axes are prepended and all
+    /// tiles end up at coordinate zero along them.
+    pub fn embed<const M: usize>(&self) -> Map<[I; M], T> {
+        assert!(M >= N, "Cannot embed {}D map into {} dimensions", N, M);
+
+        let data: HashMap<[I; M], T> = self
+            .iter()
+            .map(|(key, tile)| {
+                let mut new_key = [I::zero(); M];
+                new_key[M - N..].copy_from_slice(&key);
+                (new_key, tile.clone())
+            })
+            .collect();
+
+        Map::from(data)
+    }
+
+    /// Slice the map into a map with one dimension less by taking all tiles at
+    /// coordinate `i` along an axis
+    pub fn slice<const M: usize>(&self, i: I, axis: usize) -> Map<[I; M], T> {
+        assert_eq!(
+            M + 1,
+            N,
+            "Slicing a {}D map must produce a {}D map",
+            N,
+            N - 1
+        );
+        assert!(axis < N, "Invalid axis: {}", axis);
+
+        let drop_axis = |k: &[I; N]| {
+            let mut out = [I::zero(); M];
+            out[..axis].copy_from_slice(&k[..axis]);
+            out[axis..].copy_from_slice(&k[axis + 1..]);
+            out
+        };
+
+        let data: HashMap<[I; M], T> = self
             .iter()
-            .filter_map(|(k, t)| {
-                if k[axis] == i {
-                    Some(([k[ax0], k[ax1]], t.clone()))
-                } else {
-                    None
-                }
-            })
+            .filter(|(k, _)| k[axis] == i)
+            .map(|(k, t)| (drop_axis(&k), t.clone()))
             .collect();
 
-        let fixed_extent = self
+        let mut map = Map::from(data);
+        map.fixed_extent = self
             .fixed_extent
-            .map(|(min, max)| ([min[ax0], min[ax1]], [max[ax0], max[ax1]]));
-
-        Map { data, fixed_extent }
+            .map(|b| Bounds::new(drop_axis(&b.min), drop_axis(&b.max)));
+        map.set_neighborhood(self.neighborhood);
+        map
     }
 
-    pub fn to_vecs(&self) -> Vec<Vec<Vec<Option<T>>>> {
-        let (min, max) = self.get_extent();
+    /// Join two maps along an axis, placing the extent of `other` right after the extent
+    /// of this map and aligning their minimum corners along all other axes
+    pub fn concat(&self, other: &Self, axis: usize) -> Self {
+        assert!(axis < N, "Invalid axis: {}", axis);
 
-        num::iter::range_inclusive(min[0], max[0])
-            .map(|i| {
-                num::iter::range_inclusive(min[1], max[1])
-                    .map(|j| {
-                        num::iter::range_inclusive(min[2], max[2])
-                            .map(|k| self.data.get(&[i, j, k]).cloned())
-                            .collect()
-                    })
-                    .collect()
-            })
-            .collect()
+        let (a, b) = (self.bounds(), other.bounds());
+        let to_i64 = |i: I| i.to_i64().expect("Coordinate out of range");
+
+        let mut offset = [0; N];
+        for (k, o) in offset.iter_mut().enumerate() {
+            *o = to_i64(a.min[k]) - to_i64(b.min[k]);
+        }
+        offset[axis] = to_i64(a.max[axis]) + 1 - to_i64(b.min[axis]);
+
+        let mut out = self.clone();
+        out.fixed_extent = None;
+        out.paste(other, &offset);
+        out
     }
 }
 
-impl<T, I> std::fmt::Display for Map<[I; 3], T>
+impl<T, I, const N: usize> std::fmt::Display for Map<[I; N], T>
 where
     T: MapTile,
     I: IntCoord,
 {
+    /// Render the map with the default `Renderer`: the last two axes as a grid of tiles,
+    /// maps with more dimensions as a sequence of layers along the remaining axes.
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
-        if self.data.is_empty() {
-            return Ok(());
-        }
-
-        let (min, max) = self.get_extent();
-
-        for i in num::iter::range_inclusive(min[0], max[0]) {
-            write!(f, "Layer {} =========\n{}\n", i, self.slice(i, 0))?;
-        }
-
-        Ok(())
+        Renderer::new().write(f, self)
     }
 }
 
-////// Code for 4D maps
+////// Code for 2D maps
 
-impl<I> MapCoordinate for [I; 4]
+impl<T, I> Map<[I; 2], T>
 where
+    T: ParseMapTile,
     I: IntCoord,
 {
-    type ExtentIter = Extent4DIterator<I>;
-
-    fn elementwise_min(a: Self, b: Self) -> Self {
-        [
-            std::cmp::min(a[0], b[0]),
-            std::cmp::min(a[1], b[1]),
-            std::cmp::min(a[2], b[2]),
-            std::cmp::min(a[3], b[3]),
-        ]
+    /// Read a map from a reader, with line i and column j ending up at `[i, j]`.
+    /// Characters that are not tiles and coordinates that do not fit `I` are reported
+    /// as errors; use a [`MapReader`] to treat them as empty cells instead.
+    /// Fully populated maps will use dense storage.
+    pub fn read<R: std::io::Read>(reader: &mut R) -> MapResult<Self> {
+        MapReader::new().read(reader)
     }
+}
 
-    fn elementwise_max(a: Self, b: Self) -> Self {
-        [
-            std::cmp::max(a[0], b[0]),
-            std::cmp::max(a[1], b[1]),
-            std::cmp::max(a[2], b[2]),
-            std::cmp::max(a[3], b[3]),
-        ]
-    }
+/// How lines and columns of a text map are assigned to coordinate axes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AxisOrder {
+    /// Line i and column j end up at `[i, j]`
+    RowCol,
 
-    fn extent_iterator(min: Self, max: Self) -> Self::ExtentIter {
-        Extent4DIterator {
-            min,
-            max,
-            current: Some(min.clone()),
+    /// Line i and column j end up at `[j, i]`, i.e. x is the column and y the line
+    XY,
+}
+
+/// Builder for reading 2D maps from text
+#[derive(Debug, Clone)]
+pub struct MapReader<I> {
+    origin: [I; 2],
+    order: AxisOrder,
+    void: Vec<char>,
+    skip_invalid: bool,
+    rectangular: bool,
+}
+
+impl<I: IntCoord> Default for MapReader<I> {
+    fn default() -> Self {
+        MapReader {
+            origin: [I::zero(); 2],
+            order: AxisOrder::RowCol,
+            void: Vec::new(),
+            skip_invalid: false,
+            rectangular: false,
         }
     }
 }
 
-pub struct Extent4DIterator<I>
-where
-    I: IntCoord,
-{
-    min: [I; 4],
-    max: [I; 4],
-    current: Option<[I; 4]>,
-}
+impl<I: IntCoord> MapReader<I> {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-impl<I> Iterator for Extent4DIterator<I>
-where
-    I: IntCoord,
-{
-    type Item = [I; 4];
+    /// Set the coordinate of the first character of the first line
+    pub fn origin(mut self, origin: [I; 2]) -> Self {
+        self.origin = origin;
+        self
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some(c) = self.current {
-            let mut next = c.clone();
+    pub fn axis_order(mut self, order: AxisOrder) -> Self {
+        self.order = order;
+        self
+    }
 
-            next[0] = next[0] + I::one();
-            if next[0] > self.max[0] {
-                next[0] = self.min[0];
-                next[1] = next[1] + I::one();
-            }
+    /// Treat the given characters as empty cells instead of reporting them as unknown tiles
+    pub fn void_chars(mut self, chars: &str) -> Self {
+        self.void.extend(chars.chars());
+        self
+    }
 
-            if next[1] > self.max[1] {
-                next[1] = self.min[1];
-                next[2] = next[2] + I::one();
-            }
+    /// Silently skip unknown characters and coordinates that do not fit `I`
+    pub fn skip_invalid(mut self, skip: bool) -> Self {
+        self.skip_invalid = skip;
+        self
+    }
+
+    /// Require a non-empty input where all lines have the same number of characters.
+    /// Empty lines at the end of the input are ignored.
+    pub fn rectangular(mut self, rectangular: bool) -> Self {
+        self.rectangular = rectangular;
+        self
+    }
 
-            if next[2] > self.max[2] {
-                next[2] = self.min[2];
-                next[3] = next[3] + I::one();
+    /// Read a map from a reader. Fully populated maps will use dense storage.
+    pub fn read<T, R>(&self, reader: &mut R) -> MapResult<Map<[I; 2], T>>
+    where
+        T: ParseMapTile,
+        R: std::io::Read,
+    {
+        let mut lines = BufReader::new(reader)
+            .lines()
+            .collect::<Result<Vec<String>, _>>()
+            .context(Io)?;
+
+        if self.rectangular {
+            while lines.last().is_some_and(|l| l.is_empty()) {
+                lines.pop();
+            }
+            ensure!(!lines.is_empty(), EmptyInput);
+
+            let expected = lines[0].chars().count();
+            for (i, line) in lines.iter().enumerate() {
+                let found = line.chars().count();
+                ensure!(
+                    found == expected,
+                    RaggedRow {
+                        line: i + 1,
+                        expected,
+                        found
+                    }
+                );
             }
+        }
 
-            if next[3] > self.max[3] {
-                self.current = None
-            } else {
-                self.current = Some(next)
+        let mut data: HashMap<[I; 2], T> = HashMap::new();
+        for (i, line) in lines.iter().enumerate() {
+            for (j, c) in line.chars().enumerate() {
+                if self.void.contains(&c) {
+                    continue;
+                }
+
+                let (line, column) = (i + 1, j + 1);
+                let t = match T::from_char(c) {
+                    Some(t) => t,
+                    None if self.skip_invalid => continue,
+                    None => return UnknownTile { c, line, column }.fail(),
+                };
+
+                let pos = match self.coordinate(i, j) {
+                    Some(pos) => pos,
+                    None if self.skip_invalid => continue,
+                    None => return CoordinateOverflow { line, column }.fail(),
+                };
+
+                data.insert(pos, t);
             }
+        }
 
-            Some(c)
-        } else {
-            None
+        let mut map = Map::from(data);
+        if map.len() == map.bounds().count() {
+            map.to_dense();
         }
+
+        Ok(map)
+    }
+
+    /// Parse a map from a string
+    pub fn parse<T: ParseMapTile>(&self, s: &str) -> MapResult<Map<[I; 2], T>> {
+        self.read(&mut s.as_bytes())
+    }
+
+    fn coordinate(&self, line: usize, column: usize) -> Option<[I; 2]> {
+        let (a, b) = match self.order {
+            AxisOrder::RowCol => (line, column),
+            AxisOrder::XY => (column, line),
+        };
+
+        Some([
+            self.origin[0].checked_add(&I::from_usize(a)?)?,
+            self.origin[1].checked_add(&I::from_usize(b)?)?,
+        ])
     }
 }
 
-impl<T, I> Map<[I; 4], T>
+impl<T, I> Map<[I; 2], T>
 where
     T: MapTile,
     I: IntCoord,
 {
-    /// Convert a 3D map to a single-layered 4D map
-    pub fn from_3d(map: &Map<[I; 3], T>) -> Self {
-        let data: HashMap<[I; 4], T> = map
-            .data
-            .iter()
-            .map(|(key, tile)| {
-                let key = [I::zero(), key[0], key[1], key[2]];
-                (key, tile.clone())
-            })
-            .collect();
+    /// Rotate the map to the right, keeping the minimum corner of its extent in place
+    pub fn rotate_right(&self) -> Self {
+        self.transform(Transform::rotation(1))
+    }
 
-        Map {
-            data,
-            fixed_extent: None,
-        }
+    /// Rotate the map to the left, keeping the minimum corner of its extent in place
+    pub fn rotate_left(&self) -> Self {
+        self.transform(Transform::rotation(3))
     }
 
-    /// Slice a 4D map into a 3D map along one dimension
-    pub fn slice(&self, i: I, axis: usize) -> Map<[I; 3], T> {
-        let (ax0, ax1, ax2) = match axis {
-            0 => (1, 2, 3),
-            1 => (0, 2, 3),
-            2 => (0, 1, 3),
-            3 => (0, 1, 2),
-            _ => panic!("Invalid axis: {}", axis),
-        };
+    /// Reverse the order of tiles along an axis
+    pub fn flip(&self, axis: usize) -> Self {
+        self.transform(Transform::mirror(axis))
+    }
 
-        let data: HashMap<[I; 3], T> = self
-            .data
-            .iter()
-            .filter_map(|(k, t)| {
-                if k[axis] == i {
-                    Some(([k[ax0], k[ax1], k[ax2]], t.clone()))
-                } else {
-                    None
-                }
-            })
-            .collect();
+    /// Place another map to the right of this one
+    pub fn hconcat(&self, other: &Self) -> Self {
+        self.concat(other, 1)
+    }
 
-        let fixed_extent = self.fixed_extent.map(|(min, max)| {
-            (
-                [min[ax0], min[ax1], min[ax2]],
-                [max[ax0], max[ax1], max[ax2]],
-            )
-        });
+    /// Place another map below this one
+    pub fn vconcat(&self, other: &Self) -> Self {
+        self.concat(other, 0)
+    }
 
-        Map { data, fixed_extent }
+    /// Render the map like `Display`, highlighting the coordinates of a diff that led to
+    /// it with ANSI colors: added tiles in green, changed ones in yellow and removed
+    /// ones as a red block
+    pub fn display_diff(&self, diff: &MapDiff<[I; 2]>) -> String {
+        Renderer::new()
+            .color(true)
+            .overlay(Overlay::new(diff.added.iter().copied()).color(Color::Green))
+            .overlay(Overlay::new(diff.changed.iter().copied()).color(Color::Yellow))
+            .overlay(
+                Overlay::new(diff.removed.iter().copied())
+                    .glyph(" ")
+                    .background(Color::Red),
+            )
+            .render(self)
     }
 
-    pub fn to_vecs(&self) -> Vec<Vec<Vec<Vec<Option<T>>>>> {
+    pub fn to_vecs(&self) -> Vec<Vec<Option<T>>> {
         let (min, max) = self.get_extent();
 
         num::iter::range_inclusive(min[0], max[0])
             .map(|i| {
                 num::iter::range_inclusive(min[1], max[1])
-                    .map(|j| {
-                        num::iter::range_inclusive(min[2], max[2])
-                            .map(|k| {
-                                num::iter::range_inclusive(min[3], max[3])
-                                    .map(|l| self.data.get(&[i, j, k, l]).cloned())
-                                    .collect()
-                            })
-                            .collect()
-                    })
+                    .map(|j| self.get(&[i, j]).cloned())
                     .collect()
             })
             .collect()
     }
 }
 
-impl<T, I> std::fmt::Display for Map<[I; 4], T>
+impl<T, I> Map<[I; 3], T>
 where
     T: MapTile,
     I: IntCoord,
 {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
-        if self.data.is_empty() {
-            return Ok(());
-        }
+    /// Convert a 2D map to a single-layered 3D map
+    pub fn from_2d(map: &Map<[I; 2], T>) -> Self {
+        map.embed()
+    }
 
-        let (min, max) = self.get_extent();
+    pub fn to_vecs(&self) -> Vec<Vec<Vec<Option<T>>>> {
+        let bounds = self.bounds();
 
-        for i in num::iter::range_inclusive(min[0], max[0]) {
-            for j in num::iter::range_inclusive(min[1], max[1]) {
-                write!(
-                    f,
-                    "Layer {}, {} =========\n{}\n",
-                    i,
-                    j,
-                    self.slice(i, 0).slice(j, 0)
-                )?;
-            }
-        }
+        num::iter::range_inclusive(bounds.min[0], bounds.max[0])
+            .map(|i| {
+                let mut layer: Map<[I; 2], T> = self.slice(i, 0);
+                layer.fixed_extent = Some(Bounds::new(
+                    [bounds.min[1], bounds.min[2]],
+                    [bounds.max[1], bounds.max[2]],
+                ));
+                layer.to_vecs()
+            })
+            .collect()
+    }
+}
 
-        Ok(())
+impl<T, I> Map<[I; 4], T>
+where
+    T: MapTile,
+    I: IntCoord,
+{
+    /// Convert a 3D map to a single-layered 4D map
+    pub fn from_3d(map: &Map<[I; 3], T>) -> Self {
+        map.embed()
+    }
+
+    pub fn to_vecs(&self) -> Vec<Vec<Vec<Vec<Option<T>>>>> {
+        let bounds = self.bounds();
+
+        num::iter::range_inclusive(bounds.min[0], bounds.max[0])
+            .map(|i| {
+                let mut layer: Map<[I; 3], T> = self.slice(i, 0);
+                layer.fixed_extent = Some(Bounds::new(
+                    [bounds.min[1], bounds.min[2], bounds.min[3]],
+                    [bounds.max[1], bounds.max[2], bounds.max[3]],
+                ));
+                layer.to_vecs()
+            })
+            .collect()
+    }
+}
+
+impl<T, I> std::str::FromStr for Map<[I; 2], T>
+where
+    T: ParseMapTile,
+    I: IntCoord,
+{
+    type Err = MapError;
+
+    /// Parse a rectangular map, failing on characters that are not tiles
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MapReader::new().rectangular(true).parse(s)
     }
 }
 
@@ -737,7 +1896,10 @@ mod tests {
     #[test]
     fn test_2d_parsing() {
         let map_string = "ab \nd e";
-        let map = Map::<[usize; 2], char>::read(&mut map_string.as_bytes()).unwrap();
+        let map: Map<[usize; 2], char> = MapReader::new()
+            .void_chars(" ")
+            .read(&mut map_string.as_bytes())
+            .unwrap();
 
         assert_eq!(map.get_extent(), ([0, 0], [1, 2]));
 
@@ -750,6 +1912,85 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_2d_reader() {
+        let map: Map<[i64; 2], char> = MapReader::new()
+            .origin([-1, 5])
+            .axis_order(AxisOrder::XY)
+            .parse("ab\nc")
+            .unwrap();
+        assert_eq!(map.get(&[-1, 5]), Some(&'a'));
+        assert_eq!(map.get(&[0, 5]), Some(&'b'));
+        assert_eq!(map.get(&[-1, 6]), Some(&'c'));
+        assert_eq!(map.len(), 3);
+
+        let reader = MapReader::<u8>::new();
+        assert!(matches!(
+            reader.parse::<char>("ab\na c"),
+            Err(MapError::UnknownTile {
+                c: ' ',
+                line: 2,
+                column: 2
+            })
+        ));
+
+        let map: Map<[u8; 2], char> = reader.clone().void_chars(" ").parse("ab\na c").unwrap();
+        assert_eq!(map.len(), 4);
+        assert!(!map.is_dense());
+
+        assert!(matches!(
+            reader.origin([0, 250]).parse::<char>(&"x".repeat(8)),
+            Err(MapError::CoordinateOverflow { line: 1, column: 7 })
+        ));
+    }
+
+    #[test]
+    fn test_2d_strict_parsing() {
+        let map: Map<[u8; 2], char> = "ab\ncd\n\n".parse().unwrap();
+        assert_eq!(map.len(), 4);
+
+        assert!(matches!(
+            "abc\nd\nefg".parse::<Map<[u8; 2], char>>(),
+            Err(MapError::RaggedRow {
+                line: 2,
+                expected: 3,
+                found: 1
+            })
+        ));
+        assert!(matches!(
+            "ab\n\ncd".parse::<Map<[u8; 2], char>>(),
+            Err(MapError::RaggedRow { line: 2, .. })
+        ));
+        assert!(matches!(
+            "ab\nc d".parse::<Map<[u8; 2], char>>(),
+            Err(MapError::RaggedRow { line: 2, .. })
+        ));
+        assert!(matches!(
+            "ab\nc ".parse::<Map<[u8; 2], char>>(),
+            Err(MapError::UnknownTile {
+                c: ' ',
+                line: 2,
+                column: 2
+            })
+        ));
+        assert!(matches!(
+            "\n\n".parse::<Map<[u8; 2], char>>(),
+            Err(MapError::EmptyInput)
+        ));
+
+        // reading without the rectangular check accepts ragged and empty input,
+        // but still reports unknown tiles
+        assert!(matches!(
+            Map::<[u8; 2], char>::read(&mut "ab\nc ".as_bytes()),
+            Err(MapError::UnknownTile { c: ' ', .. })
+        ));
+        let ragged = Map::<[u8; 2], char>::read(&mut "abc\nd\nefg".as_bytes()).unwrap();
+        assert_eq!(ragged.len(), 7);
+        assert!(Map::<[u8; 2], char>::read(&mut "".as_bytes())
+            .unwrap()
+            .is_empty());
+    }
+
     #[test]
     fn test_2d_editing() {
         let mut map: Map<[usize; 2], char> = Map::new();
@@ -766,6 +2007,8 @@ mod tests {
 
         map.remove(&[8, 8]);
         assert_eq!(map.get_extent(), ([1, 1], [4, 2]));
+        map.shrink_to_fit_extent();
+        assert_eq!(map.get_extent(), ([1, 1], [4, 2]));
 
         assert_eq!(
             map.to_vecs(),
@@ -778,10 +2021,435 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_bounds() {
+        let a = Bounds::new([0usize, 0], [3, 2]);
+        let b = Bounds::new([2, 1], [5, 5]);
+
+        assert!(a.contains(&[3, 0]));
+        assert!(!a.contains(&[3, 3]));
+        assert_eq!(a.count(), 12);
+        assert_eq!(a.iter().count(), 12);
+        assert_eq!(a.into_iter().nth(4), Some([0, 1]));
+        assert_eq!(a.index_of(&[0, 1]), Some(4));
+
+        assert_eq!(a.intersection(&b), Some(Bounds::new([2, 1], [3, 2])));
+        assert_eq!(a.intersection(&Bounds::new([4, 0], [5, 0])), None);
+        assert_eq!(a.union(&b), Bounds::new([0, 0], [5, 5]));
+
+        // expanding saturates at the edge of the coordinate range
+        assert_eq!(a.expand(2), Bounds::new([0, 0], [5, 4]));
+        let signed = Bounds::new([-1i64, 0, 1], [1, 1, 1]);
+        assert_eq!(signed.expand(1), Bounds::new([-2, -1, 0], [2, 2, 2]));
+        assert_eq!(signed.expand(3).shrink(3), Some(signed));
+        assert_eq!(a.shrink(1), Some(Bounds::new([1, 1], [2, 1])));
+        assert_eq!(a.shrink(2), None);
+
+        let mut map: Map<[u8; 2], char> = Map::new();
+        assert_eq!(map.bounds(), Bounds::new([0, 0], [0, 0]));
+        map.set([5, 3], 'x');
+        map.set([2, 7], 'y');
+        assert_eq!(map.bounds(), Bounds::new([2, 3], [5, 7]));
+        map.remove(&[5, 3]);
+        map.remove(&[2, 7]);
+        assert_eq!(map.bounds(), Bounds::new([0, 0], [0, 0]));
+
+        map.fixed_extent = Some(Bounds::new([1, 1], [9, 9]));
+        assert_eq!(map.get_extent(), ([1, 1], [9, 9]));
+    }
+
+    #[test]
+    fn test_topology() {
+        let mut map: Map<[u8; 2], char> =
+            MapReader::new().void_chars(".").parse("ab.\n..c").unwrap();
+        map.set_topology(&[Topology::Bounded, Topology::Wrap]);
+        assert_eq!(map.fixed_extent, Some(Bounds::new([0, 0], [1, 2])));
+        assert_eq!(map.topology(), &[Topology::Bounded, Topology::Wrap]);
+
+        assert_eq!(map.get(&[0, 3]), Some(&'a'));
+        assert_eq!(map.get(&[1, 5]), Some(&'c'));
+        assert_eq!(map.get(&[2, 0]), None);
+        assert_eq!(map.offset(&[0, 0], &[0, -1]), Some([0, 2]));
+        assert_eq!(map.offset(&[1, 0], &[1, 0]), None);
+
+        let mut neighbors: Vec<[u8; 2]> =
+            map.neighbors(&[0, 0]).into_iter().map(|(c, _)| c).collect();
+        neighbors.sort_unstable();
+        assert_eq!(neighbors, vec![[0, 1], [1, 2]]);
+
+        map.set([0, 4], 'd');
+        map.set([5, 0], 'x');
+        assert_eq!(map.get(&[0, 1]), Some(&'d'));
+        assert_eq!(map.len(), 3);
+
+        // rays wrap around until they return to their origin
+        assert_eq!(
+            map.cast_ray(&[0, 2], &[0, 1], |_, _| true),
+            Some(([0, 0], &'a'))
+        );
+        assert_eq!(map.cast_ray(&[1, 2], &[0, 1], |_, t| *t == 'c'), None);
+        assert_eq!(map.cast_ray(&[0, 0], &[1, 0], |_, _| true), None);
+
+        map.set_topology(&[Topology::Infinite]);
+        assert!(map.topology().is_empty());
+        assert_eq!(map.get(&[0, 3]), None);
+    }
+
+    #[test]
+    fn test_rays() {
+        let map: Map<[u8; 2], char> = "#..#.\n.....\n#.x.#\n.....\n..#.#".parse().unwrap();
+        let is_wall = |_: &[u8; 2], t: &char| *t == '#';
+
+        assert_eq!(
+            map.cast_ray(&[2, 2], &[0, 1], is_wall),
+            Some(([2, 4], &'#'))
+        );
+        assert_eq!(map.cast_ray(&[2, 2], &[-1, 1], is_wall), None);
+        assert_eq!(map.cast_ray(&[2, 2], &[-1, 0], is_wall), None);
+        assert_eq!(
+            map.cast_ray(&[2, 2], &[-2, 1], is_wall),
+            Some(([0, 3], &'#'))
+        );
+        assert_eq!(map.cast_ray(&[2, 2], &[-2, -1], is_wall), None);
+        assert_eq!(map.cast_ray(&[2, 2], &[0, 0], is_wall), None);
+
+        let directions = <[u8; 2]>::neighbor_offsets(Neighborhood::Moore(1));
+        let mut visible: Vec<[u8; 2]> = map
+            .visible_from(&[2, 2], &directions, is_wall)
+            .into_iter()
+            .map(|(c, _)| c)
+            .collect();
+        visible.sort_unstable();
+        assert_eq!(visible, vec![[0, 0], [2, 0], [2, 4], [4, 2], [4, 4]]);
+
+        let mut cropped = map.clone();
+        cropped.fixed_extent = Some(Bounds::new([0, 0], [2, 3]));
+        assert_eq!(cropped.cast_ray(&[2, 2], &[0, 1], is_wall), None);
+    }
+
+    #[test]
+    fn test_diff_and_journal() {
+        let old: Map<[i32; 2], char> = "ab\nc.".parse().unwrap();
+        let mut new = old.clone();
+        new.enable_journal();
+        new.set([0, 1], 'x');
+        new.set([0, 0], 'a');
+        new.remove(&[1, 0]);
+        new.set([2, 2], 'd');
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.added, vec![[2, 2]]);
+        assert_eq!(diff.removed, vec![[1, 0]]);
+        assert_eq!(diff.changed, vec![[0, 1]]);
+        assert_eq!(diff.len(), 3);
+        assert!(new.diff(&new).is_empty());
+
+        let mut journal: Vec<[i32; 2]> = new.take_journal().unwrap().into_iter().collect();
+        journal.sort_unstable();
+        assert_eq!(journal, vec![[0, 0], [0, 1], [1, 0], [2, 2]]);
+        assert_eq!(new.take_journal(), Some(HashSet::new()));
+
+        new.disable_journal();
+        new.set([0, 0], 'z');
+        assert_eq!(new.take_journal(), None);
+
+        new.remove(&[2, 2]);
+        new.shrink_to_fit_extent();
+        assert_eq!(
+            new.display_diff(&old.diff(&new)),
+            "\x1b[33mz\x1b[0m\x1b[33mx\x1b[0m\n\x1b[41m \x1b[0m.\n"
+        );
+    }
+
+    #[test]
+    fn test_chunked_storage() {
+        let mut map: Map<[i64; 2], char> = Map::new_chunked(4);
+        for (i, c) in "abcdefgh".chars().enumerate() {
+            map.set([i as i64 * 3 - 10, 7 - i as i64 * 2], c);
+        }
+        assert!(map.is_chunked());
+        assert_eq!(map.len(), 8);
+        assert_eq!(map.get(&[-10, 7]), Some(&'a'));
+        assert_eq!(map.get(&[11, -7]), Some(&'h'));
+        assert_eq!(map.get(&[11, -6]), None);
+
+        let mut sparse = map.clone();
+        sparse.to_sparse();
+        assert!(!sparse.is_chunked());
+        assert_eq!(sparse, map);
+        let mut from_iter: Vec<([i64; 2], char)> = map.iter().map(|(k, t)| (k, *t)).collect();
+        let mut expected: Vec<([i64; 2], char)> = sparse.iter().map(|(k, t)| (k, *t)).collect();
+        from_iter.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(from_iter, expected);
+
+        let mut dense = map.clone();
+        dense.to_dense();
+        assert!(dense.is_dense());
+        assert_eq!(dense, map);
+
+        // chunks that cannot be represented leave the tiles in sparse storage
+        let mut small: Map<[u8; 2], char> = "ab\ncd".parse().unwrap();
+        small.to_sparse();
+        small.to_chunked(300);
+        assert!(!small.is_chunked());
+        assert_eq!(small.len(), 4);
+        assert_eq!(small.get(&[1, 0]), Some(&'c'));
+
+        // clones share all chunks until they are modified
+        let snapshot = map.clone();
+        map.set([-10, 6], 'x');
+        *map.get_mut(&[11, -7]).unwrap() = 'y';
+        map.remove(&[-7, 5]);
+        assert_eq!(snapshot.get(&[-7, 5]), Some(&'b'));
+        assert_eq!(snapshot.get(&[11, -7]), Some(&'h'));
+        assert_eq!(snapshot.get(&[-10, 6]), None);
+        assert_eq!(map.get(&[11, -7]), Some(&'y'));
+
+        if let (Storage::Chunked(a), Storage::Chunked(b)) = (&map.storage, &snapshot.storage) {
+            let shared = a
+                .chunks
+                .iter()
+                .filter(|(k, c)| b.chunks.get(*k).is_some_and(|d| Arc::ptr_eq(c, d)))
+                .count();
+            assert_eq!(b.chunks.len(), 6);
+            assert_eq!(a.chunks.len(), 5);
+            assert_eq!(shared, 3);
+        } else {
+            panic!("Maps should be chunked");
+        }
+
+        // emptied chunks are dropped
+        let mut single: Map<[u8; 3], bool> = Map::new_chunked(2);
+        single.set([1, 2, 3], true);
+        single.remove(&[1, 2, 3]);
+        match &single.storage {
+            Storage::Chunked(grid) => assert!(grid.chunks.is_empty()),
+            _ => panic!("Map should be chunked"),
+        }
+
+        // chunks of this size cannot represent -128
+        let mut narrow: Map<[i8; 1], bool> = Map::new_chunked(3);
+        narrow.set([5], true);
+        narrow.set([-128], true);
+        assert!(!narrow.is_chunked());
+        assert_eq!(narrow.len(), 2);
+    }
+
+    #[test]
+    fn test_2d_cropping_stitching() {
+        let map: Map<[i32; 2], char> = "abcd\nefgh\nijkl".parse().unwrap();
+
+        let cropped = map.crop(Bounds::new([1, 1], [2, 5]));
+        assert_eq!(cropped.get_extent(), ([1, 1], [2, 3]));
+        assert_eq!(cropped.to_string(), "fgh\njkl\n");
+
+        assert_eq!(map.strip_border(1).to_string(), "fg\n");
+        assert_eq!(map.strip_border(1).get_extent(), ([1, 1], [1, 2]));
+        assert!(map.strip_border(2).is_empty());
+
+        let mut pasted = map.clone();
+        pasted.paste(&map.strip_border(1), &[-1, 1]);
+        assert_eq!(pasted.to_string(), "abfg\nefgh\nijkl\n");
+
+        let small: Map<[i32; 2], char> = MapReader::new().parse("xy\nz").unwrap();
+        assert_eq!(map.hconcat(&small).to_string(), "abcdxy\nefghz \nijkl  \n");
+        assert_eq!(
+            small.vconcat(&map).to_string(),
+            "xy  \nz   \nabcd\nefgh\nijkl\n"
+        );
+
+        // the extent of the joined map starts right after the other one, including padding
+        let mut padded = Map::new();
+        padded.paste(&small, &[5, 5]);
+        padded.fixed_extent = Some(Bounds::new([4, 4], [6, 6]));
+        let joined = small.hconcat(&padded);
+        assert_eq!(joined.get_extent(), ([0, 0], [2, 4]));
+        assert_eq!(joined.get(&[1, 3]), Some(&'x'));
+        assert_eq!(joined.get(&[2, 3]), Some(&'z'));
+    }
+
+    #[test]
+    fn test_2d_dense_storage() {
+        let map = Map::<[usize; 2], char>::read(&mut "abc\ndef".as_bytes()).unwrap();
+        assert!(map.is_dense());
+        assert_eq!(map.len(), 6);
+        assert_eq!(map.get(&[1, 2]), Some(&'f'));
+
+        let partial: Map<[usize; 2], char> = MapReader::new()
+            .void_chars(" ")
+            .read(&mut "ab \nd e".as_bytes())
+            .unwrap();
+        assert!(!partial.is_dense());
+
+        let mut sparse = map.clone();
+        sparse.to_sparse();
+        assert!(!sparse.is_dense());
+        assert_eq!(sparse, map);
+        assert_eq!(format!("{}", sparse), format!("{}", map));
+        assert_eq!(sparse.find_all(&'e'), map.find_all(&'e'));
+
+        let mut removed = map.clone();
+        removed.remove(&[0, 0]);
+        assert!(removed.is_dense());
+        assert_eq!(removed.len(), 5);
+        assert_eq!(removed.get_extent(), ([0, 0], [1, 2]));
+        assert_eq!(format!("{}", removed), " bc\ndef\n");
+
+        let mut grown = map.clone();
+        grown.set([3, 1], 'x');
+        assert!(!grown.is_dense());
+        assert_eq!(grown.get(&[3, 1]), Some(&'x'));
+        assert_eq!(grown.get(&[0, 2]), Some(&'c'));
+        assert_eq!(grown.get_extent(), ([0, 0], [3, 2]));
+    }
+
+    #[test]
+    fn test_neighborhoods() {
+        let count2 = |n| <[i64; 2]>::neighbor_offsets(n).len();
+        let count3 = |n| <[i64; 3]>::neighbor_offsets(n).len();
+        let count4 = |n| <[i64; 4]>::neighbor_offsets(n).len();
+
+        assert_eq!(count2(Neighborhood::Moore(1)), 8);
+        assert_eq!(count2(Neighborhood::Moore(2)), 24);
+        assert_eq!(count2(Neighborhood::VonNeumann(1)), 4);
+        assert_eq!(count2(Neighborhood::VonNeumann(2)), 12);
+        assert_eq!(count3(Neighborhood::Moore(1)), 26);
+        assert_eq!(count3(Neighborhood::VonNeumann(1)), 6);
+        assert_eq!(count4(Neighborhood::Moore(1)), 80);
+        assert_eq!(count4(Neighborhood::VonNeumann(1)), 8);
+
+        // unsigned coordinates cannot go below zero
+        let mut corner = [0usize, 0].neighbors(Neighborhood::Moore(1));
+        corner.sort();
+        assert_eq!(corner, vec![[0, 1], [1, 0], [1, 1]]);
+    }
+
+    #[test]
+    fn test_nd_extent_iteration() {
+        let coords: Vec<[i64; 3]> = <[i64; 3]>::extent_iterator([0, -1, 5], [1, 0, 6]).collect();
+        assert_eq!(
+            coords,
+            vec![
+                [0, -1, 5],
+                [1, -1, 5],
+                [0, 0, 5],
+                [1, 0, 5],
+                [0, -1, 6],
+                [1, -1, 6],
+                [0, 0, 6],
+                [1, 0, 6],
+            ]
+        );
+
+        let (min, max) = ([-1i64, 0, 2, -3, 1], [1i64, 1, 2, -2, 3]);
+        assert_eq!(<[i64; 5]>::extent_len(min, max), 36);
+        for (i, c) in <[i64; 5]>::extent_iterator(min, max).enumerate() {
+            assert_eq!(c.extent_index(min, max), Some(i));
+        }
+        assert_eq!([2i64, 0, 2, -2, 1].extent_index(min, max), None);
+
+        assert_eq!(
+            <[i64; 5]>::neighbor_offsets(Neighborhood::Moore(1)).len(),
+            242
+        );
+        assert_eq!(
+            <[i64; 5]>::neighbor_offsets(Neighborhood::VonNeumann(1)).len(),
+            10
+        );
+    }
+
+    #[test]
+    fn test_nd_embedding_slicing() {
+        let map = "ab\ncd".parse::<Map<[i64; 2], char>>().unwrap();
+
+        let map5d: Map<[i64; 5], char> = map.embed();
+        assert_eq!(map5d.get(&[0, 0, 0, 1, 0]), Some(&'c'));
+        assert_eq!(map5d.get_extent(), ([0, 0, 0, 0, 0], [0, 0, 0, 1, 1]));
+
+        let map4d: Map<[i64; 4], char> = map5d.slice(0, 1);
+        let map3d: Map<[i64; 3], char> = map4d.slice(0, 0);
+        let map2d: Map<[i64; 2], char> = map3d.slice(0, 0);
+        assert_map_eq(&map2d, &map);
+
+        let column: Map<[i64; 1], char> = map.slice(1, 1);
+        assert_eq!(format!("{}", column), "bd\n");
+
+        assert!(map3d.slice::<2>(1, 0).is_empty());
+    }
+
+    #[test]
+    fn test_nd_wrappers() {
+        let map = "ab\nc.".parse::<Map<[i64; 2], char>>().unwrap();
+
+        let map3d = Map::<[i64; 3], char>::from_2d(&map);
+        assert_eq!(
+            map3d.to_vecs(),
+            vec![vec![vec![Some('a'), Some('b')], vec![Some('c'), Some('.')]]]
+        );
+
+        let mut map4d = Map::<[i64; 4], char>::from_3d(&map3d);
+        map4d.set([1, 0, 1, 1], 'd');
+        assert_eq!(
+            map4d.to_vecs(),
+            vec![
+                vec![vec![vec![Some('a'), Some('b')], vec![Some('c'), Some('.')]]],
+                vec![vec![vec![None, None], vec![None, Some('d')]]],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nd_display() {
+        let mut map: Map<[i64; 4], char> = Map::new();
+        map.set([0, 0, 0, 0], 'a');
+        map.set([0, 1, 1, 1], 'b');
+        map.set([1, 0, 0, 1], 'c');
+
+        assert_eq!(
+            format!("{}", map),
+            "Layer 0, 0 =========\na \n  \n\n\
+             Layer 0, 1 =========\n  \n b\n\n\
+             Layer 1, 0 =========\n c\n  \n\n\
+             Layer 1, 1 =========\n  \n  \n\n"
+        );
+    }
+
+    #[test]
+    fn test_2d_neighbors() {
+        let mut map: Map<[usize; 2], char> = MapReader::new()
+            .void_chars(" ")
+            .rectangular(true)
+            .parse("abc\nd f\nghi")
+            .unwrap();
+
+        let mut neighbors: Vec<_> = map
+            .neighbors(&[1, 1])
+            .into_iter()
+            .map(|(_, t)| *t)
+            .collect();
+        neighbors.sort();
+        assert_eq!(neighbors, vec!['a', 'b', 'c', 'd', 'f', 'g', 'h', 'i']);
+        assert_eq!(map.count_neighbors_where(&[0, 0], |_, t| *t < 'e'), 2);
+
+        map.set_neighborhood(Neighborhood::VonNeumann(1));
+        let mut neighbors: Vec<_> = map
+            .neighbors(&[1, 1])
+            .into_iter()
+            .map(|(_, t)| *t)
+            .collect();
+        neighbors.sort();
+        assert_eq!(neighbors, vec!['b', 'd', 'f', 'h']);
+        assert_eq!(map.count_neighbors_where(&[2, 2], |[i, _], _| *i == 2), 1);
+    }
+
     #[test]
     fn test_2d_display() {
         let map_string = "ab \nd e";
-        let map = Map::<[usize; 2], char>::read(&mut map_string.as_bytes()).unwrap();
+        let map: Map<[usize; 2], char> = MapReader::new()
+            .void_chars(" ")
+            .read(&mut map_string.as_bytes())
+            .unwrap();
 
         assert_eq!(format!("{}", map), format!("{}\n", map_string));
 
@@ -0,0 +1,184 @@
+use crate::map::{Map, MapCoordinate};
+
+/// Apply a function to all items of a slice using all available threads, keeping the
+/// order of the results.
+///
+/// Work is split into contiguous runs of items, one per thread. With the `rayon`
+/// feature, the rayon thread pool is used instead.
+#[cfg(not(feature = "rayon"))]
+pub fn par_map<A, U, F>(items: &[A], f: F) -> Vec<U>
+where
+    A: Sync,
+    U: Send,
+    F: Fn(&A) -> U + Sync,
+{
+    par_map_with(items, thread_count(), f)
+}
+
+/// Number of threads used by `par_map`. Tests always use several threads, so that the
+/// threaded code path is covered even on single-core hosts.
+#[cfg(not(feature = "rayon"))]
+fn thread_count() -> usize {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    if cfg!(test) {
+        threads.max(2)
+    } else {
+        threads
+    }
+}
+
+/// Apply a function to all items of a slice like `par_map`, with a given number of
+/// threads
+#[cfg_attr(feature = "rayon", allow(dead_code))]
+pub(crate) fn par_map_with<A, U, F>(items: &[A], threads: usize, f: F) -> Vec<U>
+where
+    A: Sync,
+    U: Send,
+    F: Fn(&A) -> U + Sync,
+{
+    if threads <= 1 || items.len() < 2 {
+        return items.iter().map(f).collect();
+    }
+
+    let run_len = items.len().div_ceil(threads);
+    let f = &f;
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(run_len)
+            .map(|run| scope.spawn(move || run.iter().map(f).collect::<Vec<U>>()))
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("Worker thread panicked"))
+            .collect()
+    })
+}
+
+/// Apply a function to all items of a slice using the rayon thread pool, keeping the
+/// order of the results
+#[cfg(feature = "rayon")]
+pub fn par_map<A, U, F>(items: &[A], f: F) -> Vec<U>
+where
+    A: Sync,
+    U: Send,
+    F: Fn(&A) -> U + Sync,
+{
+    use rayon::prelude::*;
+
+    items.par_iter().map(&f).collect()
+}
+
+impl<C, T> Map<C, T>
+where
+    C: MapCoordinate + Send + Sync,
+    T: Send + Sync,
+{
+    /// Compute new tiles for some coordinates in parallel from the current state of the
+    /// map, then apply all of them at once. Returning `None` clears a coordinate.
+    pub fn par_update<F>(&mut self, coords: &[C], f: F)
+    where
+        F: Fn(&Self, &C) -> Option<T> + Sync,
+    {
+        let map = &*self;
+        let updates = par_map(coords, |c| f(map, c));
+
+        for (coord, tile) in coords.iter().zip(updates) {
+            match tile {
+                Some(t) => self.set(*coord, t),
+                None => self.remove(coord),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automaton::Automaton;
+    use crate::map::Neighborhood;
+
+    #[test]
+    fn test_par_map() {
+        let items: Vec<u64> = (0..10_000).collect();
+        let squares = par_map(&items, |i| i * i);
+        assert_eq!(squares, items.iter().map(|i| i * i).collect::<Vec<_>>());
+        assert!(par_map(&[] as &[u64], |i| *i).is_empty());
+
+        for threads in [1, 2, 3, 16] {
+            assert_eq!(par_map_with(&items[..7], threads, |i| i * i), squares[..7]);
+        }
+    }
+
+    #[test]
+    fn test_par_update() {
+        let mut map: Map<[i32; 2], u32> = Map::new();
+        for i in 0..50 {
+            for j in 0..50 {
+                map.set([i, j], (i * j) as u32 % 7);
+            }
+        }
+
+        // blur with the neighbors, reading only the previous state
+        let blur = |m: &Map<[i32; 2], u32>, c: &[i32; 2]| {
+            let sum: u32 = m.neighbors(c).iter().map(|(_, t)| **t).sum();
+            if sum == 0 {
+                None
+            } else {
+                Some(sum % 10)
+            }
+        };
+
+        let coords: Vec<[i32; 2]> = map.keys().collect();
+        let mut sequential = map.clone();
+        for c in &coords {
+            let next = blur(&map, c);
+            match next {
+                Some(t) => sequential.set(*c, t),
+                None => sequential.remove(c),
+            }
+        }
+
+        map.par_update(&coords, blur);
+        assert_eq!(map, sequential);
+    }
+
+    #[test]
+    fn test_par_step() {
+        let life = |_: &[i64; 3], tile: Option<&bool>, neighbors: &[&bool]| match (
+            tile.is_some(),
+            neighbors.len(),
+        ) {
+            (true, 2..=3) | (false, 3) => Some(true),
+            _ => None,
+        };
+
+        let mut map = Map::new();
+        for (i, c) in ".#.\n..#\n###".lines().enumerate() {
+            for (j, c) in c.chars().enumerate() {
+                if c == '#' {
+                    map.set([0, i as i64, j as i64], true);
+                }
+            }
+        }
+
+        // only the stepping mode differs
+        for incremental in [false, true] {
+            let automaton = || {
+                let a = Automaton::new(map.clone(), Neighborhood::Moore(1), life).growing();
+                if incremental {
+                    a.incremental()
+                } else {
+                    a
+                }
+            };
+            let (mut sequential, mut parallel) = (automaton(), automaton());
+
+            for _ in 0..6 {
+                assert_eq!(parallel.par_step(), sequential.step());
+                assert_eq!(parallel.map, sequential.map);
+            }
+            assert_eq!(parallel.map.len(), 112);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use crate::map::{Bounds, IntCoord, Map, MapTile};
+use crate::transform::Transform;
+
+/// A place where a pattern occurs within a map
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PatternMatch<I> {
+    /// Position in the map of the minimum corner of the transformed pattern
+    pub offset: [I; 2],
+
+    /// Transform that was applied to the pattern
+    pub transform: Transform,
+}
+
+/// Tiles of a pattern relative to its minimum corner, along with the size of the pattern
+struct Template<I, T> {
+    transform: Transform,
+    size: [I; 2],
+    tiles: Vec<([I; 2], T)>,
+}
+
+impl<I: IntCoord, T: MapTile> Template<I, T> {
+    fn new(pattern: &Map<[I; 2], T>, transform: Transform) -> Self {
+        let pattern = pattern.transform(transform);
+        let Bounds { min, max } = pattern.bounds();
+
+        let tiles = pattern
+            .iter()
+            .map(|([i, j], t)| ([i - min[0], j - min[1]], t.clone()))
+            .collect();
+
+        Template {
+            transform,
+            size: [max[0] - min[0], max[1] - min[1]],
+            tiles,
+        }
+    }
+}
+
+impl<T, I> Map<[I; 2], T>
+where
+    T: MapTile,
+    I: IntCoord,
+{
+    /// Get the templates of a pattern, optionally in all orientations
+    fn templates(pattern: &Map<[I; 2], T>, orientations: bool) -> Vec<Template<I, T>> {
+        let transforms = if orientations {
+            Transform::all().to_vec()
+        } else {
+            vec![Transform::identity()]
+        };
+
+        transforms
+            .into_iter()
+            .map(|t| Template::new(pattern, t))
+            .collect()
+    }
+
+    /// Find all places where a pattern occurs, comparing tiles using a predicate that
+    /// gets the map tile and the pattern tile. Undefined cells in the pattern act as
+    /// wildcards. If `orientations` is set, the pattern is also tried in all rotations
+    /// and flips. Symmetric patterns will match once for every orientation that fits.
+    pub fn find_pattern_where<P>(
+        &self,
+        pattern: &Map<[I; 2], T>,
+        orientations: bool,
+        predicate: P,
+    ) -> Vec<PatternMatch<I>>
+    where
+        P: Fn(&T, &T) -> bool,
+    {
+        let mut out = Vec::new();
+        if self.is_empty() || pattern.is_empty() {
+            return out;
+        }
+
+        let Bounds { min, max } = self.bounds();
+        for template in Self::templates(pattern, orientations) {
+            if max[0] - min[0] < template.size[0] || max[1] - min[1] < template.size[1] {
+                continue;
+            }
+
+            let last = [max[0] - template.size[0], max[1] - template.size[1]];
+            for offset in Bounds::new(min, last) {
+                let found = template.tiles.iter().all(|([i, j], pt)| {
+                    match self.get(&[offset[0] + *i, offset[1] + *j]) {
+                        Some(t) => predicate(t, pt),
+                        None => false,
+                    }
+                });
+
+                if found {
+                    out.push(PatternMatch {
+                        offset,
+                        transform: template.transform,
+                    });
+                }
+            }
+        }
+        out
+    }
+
+    /// Get the coordinates of the cells covered by matched patterns
+    pub fn match_coords(pattern: &Map<[I; 2], T>, matches: &[PatternMatch<I>]) -> Vec<[I; 2]> {
+        let mut out = Vec::new();
+        for m in matches {
+            let template = Template::new(pattern, m.transform);
+            for ([i, j], _) in template.tiles {
+                out.push([m.offset[0] + i, m.offset[1] + j]);
+            }
+        }
+        out
+    }
+
+    /// Overwrite the cells covered by matched patterns with a tile
+    pub fn overwrite_matches(
+        &mut self,
+        pattern: &Map<[I; 2], T>,
+        matches: &[PatternMatch<I>],
+        tile: T,
+    ) {
+        for pos in Self::match_coords(pattern, matches) {
+            self.set(pos, tile.clone());
+        }
+    }
+}
+
+impl<T, I> Map<[I; 2], T>
+where
+    T: MapTile + PartialEq,
+    I: IntCoord,
+{
+    /// Find all places where a pattern occurs. Undefined cells in the pattern act as
+    /// wildcards. If `orientations` is set, the pattern is also tried in all rotations
+    /// and flips.
+    pub fn find_pattern(
+        &self,
+        pattern: &Map<[I; 2], T>,
+        orientations: bool,
+    ) -> Vec<PatternMatch<I>> {
+        self.find_pattern_where(pattern, orientations, |t, pt| t == pt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::MapReader;
+
+    #[test]
+    fn test_find_pattern() {
+        let map: Map<[usize; 2], char> = "#..#.\n.##..\n..#.#\n.##..".parse().unwrap();
+        let pattern: Map<[usize; 2], char> =
+            MapReader::new().void_chars(" ").parse("# \n ##").unwrap();
+
+        let matches = map.find_pattern(&pattern, false);
+        assert_eq!(
+            matches,
+            vec![PatternMatch {
+                offset: [0, 0],
+                transform: Transform::identity()
+            }]
+        );
+
+        let mut found: Vec<_> = map
+            .find_pattern(&pattern, true)
+            .into_iter()
+            .map(|m| (m.offset, m.transform.is_flipped(), m.transform.rotations()))
+            .collect();
+        found.sort();
+        assert_eq!(
+            found,
+            vec![
+                ([0, 0], false, 0),
+                ([0, 1], true, 2),
+                ([0, 2], false, 1),
+                ([1, 1], false, 3),
+                ([1, 1], true, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overwrite_matches() {
+        let mut map: Map<[i64; 2], char> = "....\n.##.\n..#.".parse().unwrap();
+        let pattern: Map<[i64; 2], char> =
+            MapReader::new().void_chars(" ").parse("##\n #").unwrap();
+
+        // the pattern is symmetric along a diagonal, so it matches in two orientations
+        let matches = map.find_pattern_where(&pattern, true, |t, _| t == &'#');
+        assert_eq!(matches.len(), 2);
+        assert!(matches.iter().all(|m| m.offset == [1, 1]));
+
+        let mut coords = Map::match_coords(&pattern, &matches);
+        coords.sort_unstable();
+        coords.dedup();
+        assert_eq!(coords, vec![[1, 1], [1, 2], [2, 2]]);
+
+        map.overwrite_matches(&pattern, &matches, 'O');
+        assert_eq!(format!("{}", map), "....\n.OO.\n..O.\n");
+    }
+}
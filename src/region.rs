@@ -0,0 +1,300 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::map::{Bounds, Map, MapCoordinate, Neighborhood, Topology};
+
+/// A connected set of coordinates in a map
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region<C: MapCoordinate> {
+    coords: HashSet<C>,
+    bounds: Bounds<C>,
+    /// Extent and topology of the map the region was taken from, so that neighbors
+    /// across wrapping edges are found like on the map
+    extent: Bounds<C>,
+    topology: Vec<Topology>,
+}
+
+impl<C: MapCoordinate> Region<C> {
+    fn new<T>(coords: HashSet<C>, map: &Map<C, T>) -> Option<Self> {
+        let bounds = Bounds::from_coords(coords.iter().cloned())?;
+        Some(Region {
+            coords,
+            bounds,
+            extent: map.bounds(),
+            topology: map.topology().to_vec(),
+        })
+    }
+
+    /// Translate a coordinate like `Map::offset` on the map of the region
+    fn offset(&self, coord: &C, by: &C::Offset) -> Option<C> {
+        if self.topology.is_empty() {
+            coord.offset(by)
+        } else {
+            coord.offset_within(by, &self.extent, &self.topology)
+        }
+    }
+
+    /// Get the number of coordinates in the region
+    pub fn area(&self) -> usize {
+        self.coords.len()
+    }
+
+    /// Get the number of sides between the region and coordinates outside of it, i.e.
+    /// edges for 2D maps, faces for 3D maps and hexagon sides for hex maps
+    pub fn perimeter(&self) -> usize {
+        let offsets = C::neighbor_offsets(Neighborhood::VonNeumann(1));
+
+        self.coords
+            .iter()
+            .map(|c| {
+                offsets
+                    .iter()
+                    .filter(|ofs| {
+                        self.offset(c, ofs)
+                            .is_none_or(|n| !self.coords.contains(&n))
+                    })
+                    .count()
+            })
+            .sum()
+    }
+
+    /// Get the bounding box of the region
+    pub fn bounds(&self) -> Bounds<C> {
+        self.bounds
+    }
+
+    pub fn contains(&self, coord: &C) -> bool {
+        self.coords.contains(coord)
+    }
+
+    /// Iterate over the coordinates of the region in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = &C> + '_ {
+        self.coords.iter()
+    }
+}
+
+impl<C: MapCoordinate, T> Map<C, T> {
+    /// Collect all tiles connected to `start`, where `joins` decides whether a tile
+    /// connects to a neighboring tile
+    fn flood(
+        &self,
+        start: C,
+        connectivity: Neighborhood,
+        joins: &dyn Fn(&C, &T, &C, &T) -> bool,
+    ) -> HashSet<C> {
+        let offsets = C::neighbor_offsets(connectivity);
+
+        let mut coords = HashSet::new();
+        let mut queue = VecDeque::new();
+        coords.insert(start);
+        queue.push_back(start);
+
+        while let Some(pos) = queue.pop_front() {
+            let tile = &self[&pos];
+            for ofs in &offsets {
+                let n = match self.offset(&pos, ofs) {
+                    Some(n) if !coords.contains(&n) => n,
+                    _ => continue,
+                };
+
+                if let Some(t) = self.get(&n) {
+                    if joins(&pos, tile, &n, t) {
+                        coords.insert(n);
+                        queue.push_back(n);
+                    }
+                }
+            }
+        }
+
+        coords
+    }
+
+    /// Get the region of tiles matching a predicate that is connected to `start`,
+    /// or `None` if the tile at `start` is undefined or does not match
+    pub fn flood_fill_where<P: Fn(&C, &T) -> bool>(
+        &self,
+        start: &C,
+        connectivity: Neighborhood,
+        predicate: P,
+    ) -> Option<Region<C>> {
+        let start = self.wrap(start)?;
+        if !predicate(&start, self.get(&start)?) {
+            return None;
+        }
+
+        let joins = |_: &C, _: &T, c: &C, t: &T| predicate(c, t);
+        Region::new(self.flood(start, connectivity, &joins), self)
+    }
+
+    /// Split all tiles matching a predicate into connected regions, in no particular order
+    pub fn connected_components_where<P: Fn(&C, &T) -> bool>(
+        &self,
+        connectivity: Neighborhood,
+        predicate: P,
+    ) -> Vec<Region<C>> {
+        let joins = |_: &C, _: &T, c: &C, t: &T| predicate(c, t);
+        self.components(connectivity, |c, t| predicate(c, t), &joins)
+    }
+
+    /// Label all tiles matching a predicate with the index of their region in the
+    /// output of `connected_components_where`
+    pub fn label_components_where<P: Fn(&C, &T) -> bool>(
+        &self,
+        connectivity: Neighborhood,
+        predicate: P,
+    ) -> (Map<C, usize>, Vec<Region<C>>) {
+        let regions = self.connected_components_where(connectivity, predicate);
+        (label(&regions), regions)
+    }
+
+    fn components<P: Fn(&C, &T) -> bool>(
+        &self,
+        connectivity: Neighborhood,
+        predicate: P,
+        joins: &dyn Fn(&C, &T, &C, &T) -> bool,
+    ) -> Vec<Region<C>> {
+        let mut visited: HashSet<C> = HashSet::new();
+        let mut regions = Vec::new();
+
+        for (pos, tile) in self.iter() {
+            if visited.contains(&pos) || !predicate(&pos, tile) {
+                continue;
+            }
+
+            let coords = self.flood(pos, connectivity, joins);
+            visited.extend(coords.iter().cloned());
+            regions.extend(Region::new(coords, self));
+        }
+
+        regions
+    }
+}
+
+impl<C: MapCoordinate, T: PartialEq> Map<C, T> {
+    /// Get the region of tiles equal to the tile at `start` that is connected to it
+    pub fn flood_fill(&self, start: &C, connectivity: Neighborhood) -> Option<Region<C>> {
+        let tile = self.get(start)?;
+        self.flood_fill_where(start, connectivity, |_, t| t == tile)
+    }
+
+    /// Split the map into connected regions of equal tiles, in no particular order
+    pub fn connected_components(&self, connectivity: Neighborhood) -> Vec<Region<C>> {
+        let joins = |_: &C, a: &T, _: &C, b: &T| a == b;
+        self.components(connectivity, |_, _| true, &joins)
+    }
+
+    /// Label all tiles with the index of their region in the output of
+    /// `connected_components`
+    pub fn label_components(&self, connectivity: Neighborhood) -> (Map<C, usize>, Vec<Region<C>>) {
+        let regions = self.connected_components(connectivity);
+        (label(&regions), regions)
+    }
+}
+
+fn label<C: MapCoordinate>(regions: &[Region<C>]) -> Map<C, usize> {
+    let mut labels = Map::new();
+    for (i, region) in regions.iter().enumerate() {
+        for c in region.iter() {
+            labels.set(*c, i);
+        }
+    }
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex::Hex;
+
+    fn sample() -> Map<[i32; 2], char> {
+        "aab.\n.ab.\nb..b\n...b".parse().unwrap()
+    }
+
+    #[test]
+    fn test_flood_fill() {
+        let map = sample();
+
+        let a = map
+            .flood_fill(&[0, 0], Neighborhood::VonNeumann(1))
+            .unwrap();
+        assert_eq!(a.area(), 3);
+        assert_eq!(a.perimeter(), 8);
+        assert_eq!(a.bounds(), Bounds::new([0, 0], [1, 1]));
+        assert!(a.contains(&[1, 1]));
+
+        // the b at [1, 2] only touches [2, 3] diagonally
+        let b = map
+            .flood_fill(&[0, 2], Neighborhood::VonNeumann(1))
+            .unwrap();
+        assert_eq!(b.area(), 2);
+        let b = map.flood_fill(&[0, 2], Neighborhood::Moore(1)).unwrap();
+        assert_eq!(b.area(), 4);
+        assert_eq!(b.bounds(), Bounds::new([0, 2], [3, 3]));
+
+        let floor = map.flood_fill_where(&[2, 1], Neighborhood::VonNeumann(1), |_, t| *t != 'b');
+        assert_eq!(floor.unwrap().area(), 9);
+        assert!(map
+            .flood_fill_where(&[2, 0], Neighborhood::VonNeumann(1), |_, t| *t == '.')
+            .is_none());
+        assert!(map.flood_fill(&[9, 9], Neighborhood::Moore(1)).is_none());
+    }
+
+    #[test]
+    fn test_components() {
+        let map = sample();
+
+        let mut areas: Vec<usize> = map
+            .connected_components(Neighborhood::VonNeumann(1))
+            .iter()
+            .map(|r| r.area())
+            .collect();
+        areas.sort_unstable();
+        assert_eq!(areas, vec![1, 1, 2, 2, 2, 3, 5]);
+
+        let (labels, regions) =
+            map.label_components_where(Neighborhood::Moore(1), |_, t| *t == 'b');
+        assert_eq!(regions.len(), 2);
+        assert_eq!(labels.len(), 5);
+        assert_eq!(labels[&[0, 2]], labels[&[3, 3]]);
+        assert_ne!(labels[&[0, 2]], labels[&[2, 0]]);
+        assert_eq!(regions[labels[&[2, 0]]].area(), 1);
+    }
+
+    #[test]
+    fn test_wrapping_regions() {
+        let mut map: Map<[i32; 2], char> = "aaaa\nbbbb".parse().unwrap();
+        map.set_topology(&[Topology::Bounded, Topology::Wrap]);
+
+        let a = map
+            .flood_fill(&[0, 5], Neighborhood::VonNeumann(1))
+            .unwrap();
+        assert_eq!(a.area(), 4);
+        assert!(a.contains(&[0, 1]));
+        assert!(!a.contains(&[0, 5]));
+        // the row joins up with itself across the wrapping axis
+        assert_eq!(a.perimeter(), 8);
+
+        assert!(map
+            .flood_fill(&[2, 0], Neighborhood::VonNeumann(1))
+            .is_none());
+    }
+
+    #[test]
+    fn test_hex_regions() {
+        let mut map: Map<Hex, bool> = Map::new();
+        for h in Hex::default().ring(1) {
+            map.set(h, true);
+        }
+        map.set(Hex::new(5, 5), true);
+
+        let regions = map.connected_components(Neighborhood::default());
+        let mut areas: Vec<usize> = regions.iter().map(|r| r.area()).collect();
+        areas.sort_unstable();
+        assert_eq!(areas, vec![1, 6]);
+
+        let ring = map
+            .flood_fill(&Hex::new(1, 0), Neighborhood::default())
+            .unwrap();
+        assert_eq!(ring.perimeter(), 6 * 6 - 2 * 6);
+        assert!(!ring.contains(&Hex::default()));
+    }
+}
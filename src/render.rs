@@ -0,0 +1,538 @@
+use std::collections::HashSet;
+use std::fmt::{self, Write};
+
+use crate::map::{Bounds, IntCoord, Map, MapCoordinate, MapTile};
+
+/// Terminal colors used by the renderer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// The ANSI SGR parameters of the color, relative to the foreground (30) or
+    /// background (40) base
+    fn sgr(self, base: u8) -> String {
+        let index = match self {
+            Color::Black => 0,
+            Color::Red => 1,
+            Color::Green => 2,
+            Color::Yellow => 3,
+            Color::Blue => 4,
+            Color::Magenta => 5,
+            Color::Cyan => 6,
+            Color::White => 7,
+            Color::Rgb(r, g, b) => return format!("{};2;{};{};{}", base + 8, r, g, b),
+        };
+        (base + index).to_string()
+    }
+}
+
+/// A set of coordinates drawn on top of a map, e.g. a path or pattern matches
+#[derive(Debug, Clone)]
+pub struct Overlay<C> {
+    coords: HashSet<C>,
+    glyph: Option<String>,
+    foreground: Option<Color>,
+    background: Option<Color>,
+    label: Option<String>,
+}
+
+impl<C: MapCoordinate> Overlay<C> {
+    pub fn new<It: IntoIterator<Item = C>>(coords: It) -> Self {
+        Overlay {
+            coords: coords.into_iter().collect(),
+            glyph: None,
+            foreground: None,
+            background: None,
+            label: None,
+        }
+    }
+
+    /// Draw this text instead of the tiles
+    pub fn glyph(mut self, glyph: &str) -> Self {
+        self.glyph = Some(glyph.to_string());
+        self
+    }
+
+    /// Draw the covered cells in a color
+    pub fn color(mut self, color: Color) -> Self {
+        self.foreground = Some(color);
+        self
+    }
+
+    /// Draw the covered cells on a background color
+    pub fn background(mut self, color: Color) -> Self {
+        self.background = Some(color);
+        self
+    }
+
+    /// Describe the overlay in the legend
+    pub fn label(mut self, label: &str) -> Self {
+        self.label = Some(label.to_string());
+        self
+    }
+}
+
+/// Text rendering of maps. The last two axes are drawn as a grid, maps with more
+/// dimensions as a sequence of layers along the remaining axes.
+#[derive(Debug, Clone)]
+pub struct Renderer<C> {
+    rulers: bool,
+    color: bool,
+    legend: bool,
+    tile_width: usize,
+    separator: Option<String>,
+    void: String,
+    overlays: Vec<Overlay<C>>,
+    entries: Vec<(String, String)>,
+}
+
+impl<C: MapCoordinate> Default for Renderer<C> {
+    fn default() -> Self {
+        Renderer {
+            rulers: false,
+            color: false,
+            legend: false,
+            tile_width: 1,
+            separator: None,
+            void: " ".to_string(),
+            overlays: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl<C: MapCoordinate> Renderer<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Label rows and columns with their coordinates
+    pub fn rulers(mut self, rulers: bool) -> Self {
+        self.rulers = rulers;
+        self
+    }
+
+    /// Use ANSI escape codes for the colors of overlays
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// List the labelled overlays and legend entries below the map
+    pub fn legend(mut self, legend: bool) -> Self {
+        self.legend = legend;
+        self
+    }
+
+    /// Add a legend entry for a tile
+    pub fn legend_entry(mut self, tile: &str, description: &str) -> Self {
+        self.entries
+            .push((tile.to_string(), description.to_string()));
+        self
+    }
+
+    /// Minimum number of characters per cell. Cells are widened to fit the widest
+    /// tile and right-aligned.
+    pub fn tile_width(mut self, width: usize) -> Self {
+        self.tile_width = width;
+        self
+    }
+
+    /// Text between neighboring cells. Defaults to nothing for single character cells
+    /// and a space otherwise.
+    pub fn separator(mut self, separator: &str) -> Self {
+        self.separator = Some(separator.to_string());
+        self
+    }
+
+    /// Text for cells without a tile
+    pub fn void(mut self, void: &str) -> Self {
+        self.void = void.to_string();
+        self
+    }
+
+    /// Draw an overlay. Later overlays are drawn on top of earlier ones.
+    pub fn overlay(mut self, overlay: Overlay<C>) -> Self {
+        self.overlays.push(overlay);
+        self
+    }
+
+    fn paint(&self, text: &str, foreground: Option<Color>, background: Option<Color>) -> String {
+        let codes: Vec<String> = foreground
+            .map(|c| c.sgr(30))
+            .into_iter()
+            .chain(background.map(|c| c.sgr(40)))
+            .collect();
+
+        if !self.color || codes.is_empty() {
+            text.to_string()
+        } else {
+            format!("\x1b[{}m{}\x1b[0m", codes.join(";"), text)
+        }
+    }
+}
+
+impl<I: IntCoord, const N: usize> Renderer<[I; N]> {
+    /// Render a map to a string
+    pub fn render<T: MapTile>(&self, map: &Map<[I; N], T>) -> String {
+        let mut out = String::new();
+        self.write(&mut out, map)
+            .expect("writing to a string cannot fail");
+        out
+    }
+
+    /// Render a map. Overlays outside of the map's extent enlarge the drawn area.
+    /// Maps without axes have no cells to draw and render as nothing.
+    pub fn write<W: Write, T: MapTile>(&self, out: &mut W, map: &Map<[I; N], T>) -> fmt::Result {
+        if N == 0 {
+            return Ok(());
+        }
+
+        let mut bounds = if map.is_empty() {
+            None
+        } else {
+            Some(map.bounds())
+        };
+        for c in self.overlays.iter().flat_map(|o| o.coords.iter()) {
+            bounds = Some(match bounds {
+                Some(mut b) => {
+                    b.include(*c);
+                    b
+                }
+                None => Bounds::new(*c, *c),
+            });
+        }
+        let Bounds { min, max } = match bounds {
+            Some(b) => b,
+            None => return Ok(()),
+        };
+
+        let width = map
+            .values()
+            .map(|t| t.to_string().chars().count())
+            .chain(
+                self.overlays
+                    .iter()
+                    .filter_map(|o| o.glyph.as_ref())
+                    .map(|g| g.chars().count()),
+            )
+            .chain(std::iter::once(self.void.chars().count()))
+            .chain(std::iter::once(self.tile_width))
+            .max()
+            .unwrap_or(1);
+        let separator = match &self.separator {
+            Some(s) => s.as_str(),
+            None if width > 1 => " ",
+            None => "",
+        };
+
+        let ax1 = N - 1;
+        let ax0 = if N > 1 { Some(N - 2) } else { None };
+        let layer_axes = N.saturating_sub(2);
+
+        let label_width = |axis: usize| {
+            num::iter::range_inclusive(min[axis], max[axis])
+                .map(|i| i.to_string().len())
+                .max()
+                .unwrap_or(0)
+        };
+        let row_labels = match ax0 {
+            Some(ax0) if self.rulers => label_width(ax0),
+            _ => 0,
+        };
+        let indent = if ax0.is_some() && self.rulers {
+            " ".repeat(row_labels + 1)
+        } else {
+            String::new()
+        };
+
+        let mut pos = min;
+        loop {
+            if N > 2 {
+                let layer: Vec<String> = pos[..layer_axes].iter().map(|i| i.to_string()).collect();
+                writeln!(out, "Layer {} =========", layer.join(", "))?;
+            }
+
+            if self.rulers {
+                self.write_ruler(out, min[ax1], max[ax1], &indent, width, separator)?;
+            }
+
+            let rows: Vec<Option<I>> = match ax0 {
+                Some(ax0) => num::iter::range_inclusive(min[ax0], max[ax0])
+                    .map(Some)
+                    .collect(),
+                None => vec![None],
+            };
+            for row in rows {
+                if let (Some(ax0), Some(i)) = (ax0, row) {
+                    pos[ax0] = i;
+                    if self.rulers {
+                        write!(out, "{:>w$} ", i.to_string(), w = row_labels)?;
+                    }
+                }
+
+                for j in num::iter::range_inclusive(min[ax1], max[ax1]) {
+                    if j != min[ax1] {
+                        out.write_str(separator)?;
+                    }
+                    pos[ax1] = j;
+                    self.write_cell(out, map, &pos, width)?;
+                }
+                writeln!(out)?;
+            }
+
+            if N > 2 {
+                writeln!(out)?;
+            }
+
+            // advance to the next layer, with the last layer axis varying fastest
+            let mut axis = layer_axes;
+            let done = loop {
+                if axis == 0 {
+                    break true;
+                }
+                axis -= 1;
+
+                if pos[axis] < max[axis] {
+                    pos[axis] = pos[axis] + I::one();
+                    break false;
+                }
+                pos[axis] = min[axis];
+            };
+            if done {
+                break;
+            }
+        }
+
+        if self.legend {
+            self.write_legend(out, width, N <= 2)?;
+        }
+        Ok(())
+    }
+
+    fn write_cell<W: Write, T: MapTile>(
+        &self,
+        out: &mut W,
+        map: &Map<[I; N], T>,
+        pos: &[I; N],
+        width: usize,
+    ) -> fmt::Result {
+        let (mut glyph, mut foreground, mut background) = (None, None, None);
+        for o in self
+            .overlays
+            .iter()
+            .rev()
+            .filter(|o| o.coords.contains(pos))
+        {
+            glyph = glyph.or_else(|| o.glyph.clone());
+            foreground = foreground.or(o.foreground);
+            background = background.or(o.background);
+        }
+
+        let text = glyph.unwrap_or_else(|| match map.get(pos) {
+            Some(t) => t.to_string(),
+            None => self.void.clone(),
+        });
+        let padding = width.saturating_sub(text.chars().count());
+        write!(
+            out,
+            "{}{}",
+            " ".repeat(padding),
+            self.paint(&text, foreground, background)
+        )
+    }
+
+    /// Write the column coordinates, stacked vertically if they are wider than the cells
+    fn write_ruler<W: Write>(
+        &self,
+        out: &mut W,
+        from: I,
+        to: I,
+        indent: &str,
+        width: usize,
+        separator: &str,
+    ) -> fmt::Result {
+        let labels: Vec<String> = num::iter::range_inclusive(from, to)
+            .map(|i| i.to_string())
+            .collect();
+        let height = labels.iter().map(|l| l.len()).max().unwrap_or(0);
+
+        let lines: Vec<Vec<String>> = if height <= width {
+            vec![labels]
+        } else {
+            (0..height)
+                .map(|k| {
+                    labels
+                        .iter()
+                        .map(|l| {
+                            let padded = format!("{:>h$}", l, h = height);
+                            padded[k..k + 1].to_string()
+                        })
+                        .collect()
+                })
+                .collect()
+        };
+
+        for line in lines {
+            let cells: Vec<String> = line
+                .iter()
+                .map(|l| format!("{:>w$}", l, w = width))
+                .collect();
+            writeln!(
+                out,
+                "{}",
+                format!("{}{}", indent, cells.join(separator)).trim_end()
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_legend<W: Write>(&self, out: &mut W, width: usize, spacing: bool) -> fmt::Result {
+        let entries: Vec<(String, &str)> = self
+            .entries
+            .iter()
+            .map(|(tile, description)| (format!("{:>w$}", tile, w = width), description.as_str()))
+            .chain(self.overlays.iter().filter_map(|o| {
+                let label = o.label.as_ref()?;
+                let glyph = o.glyph.as_deref().unwrap_or("*");
+                let padding = " ".repeat(width.saturating_sub(glyph.chars().count()));
+                let glyph = self.paint(glyph, o.foreground, o.background);
+                Some((padding + &glyph, label.as_str()))
+            }))
+            .collect();
+
+        if entries.is_empty() {
+            return Ok(());
+        }
+        if spacing {
+            writeln!(out)?;
+        }
+        for (glyph, description) in entries {
+            writeln!(out, "{} {}", glyph, description)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::MapReader;
+
+    fn small() -> Map<[i32; 2], char> {
+        MapReader::new().parse("ab\ncd").unwrap()
+    }
+
+    #[test]
+    fn test_default() {
+        let map = small();
+        assert_eq!(Renderer::new().render(&map), map.to_string());
+        assert_eq!(Renderer::new().render(&map), "ab\ncd\n");
+        assert_eq!(Renderer::new().render(&Map::<[i32; 2], char>::new()), "");
+
+        let mut line: Map<[i32; 1], char> = Map::new();
+        line.set([0], 'x');
+        line.set([2], 'y');
+        assert_eq!(Renderer::new().void(".").render(&line), "x.y\n");
+
+        let mut point: Map<[i32; 0], char> = Map::new();
+        point.set([], 'p');
+        assert_eq!(Renderer::new().render(&point), "");
+    }
+
+    #[test]
+    fn test_rulers() {
+        let mut map = small();
+        map.set([-1, 11], 'e');
+        assert_eq!(
+            Renderer::new().rulers(true).render(&map),
+            concat!(
+                "             11\n",
+                "   012345678901\n",
+                "-1            e\n",
+                " 0 ab          \n",
+                " 1 cd          \n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_wide_tiles() {
+        let mut map: Map<[u8; 2], u32> = Map::new();
+        map.set([0, 0], 1);
+        map.set([0, 1], 100);
+        map.set([1, 1], 20);
+        assert_eq!(Renderer::new().render(&map), "  1 100\n     20\n");
+        assert_eq!(
+            Renderer::new().rulers(true).void(".").render(&map),
+            concat!("    0   1\n", "0   1 100\n", "1   .  20\n")
+        );
+        assert_eq!(
+            Renderer::new().tile_width(4).separator("|").render(&map),
+            "   1| 100\n    |  20\n"
+        );
+    }
+
+    #[test]
+    fn test_overlays() {
+        let map = small();
+        let path = Overlay::new(vec![[0, 0], [1, 0], [2, 0]])
+            .glyph("*")
+            .color(Color::Red)
+            .label("path");
+        let highlight = Overlay::new(vec![[0, 0], [0, 1]]).background(Color::Rgb(0, 0, 255));
+
+        let renderer = Renderer::new()
+            .overlay(path)
+            .overlay(highlight)
+            .legend_entry("a", "start");
+        assert_eq!(renderer.render(&map), "*b\n*d\n* \n");
+        assert_eq!(
+            renderer.clone().legend(true).render(&map),
+            "*b\n*d\n* \n\na start\n* path\n"
+        );
+        assert_eq!(
+            renderer.color(true).render(&map),
+            concat!(
+                "\x1b[31;48;2;0;0;255m*\x1b[0m\x1b[48;2;0;0;255mb\x1b[0m\n",
+                "\x1b[31m*\x1b[0md\n",
+                "\x1b[31m*\x1b[0m \n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_layers() {
+        let mut map: Map<[i8; 3], char> = Map::new();
+        map.set([0, 0, 0], 'a');
+        map.set([1, 0, 1], 'b');
+        let renderer = Renderer::new()
+            .rulers(true)
+            .legend(true)
+            .overlay(Overlay::new(vec![[1, 0, 0]]).glyph("#").label("wall"));
+        assert_eq!(
+            renderer.render(&map),
+            concat!(
+                "Layer 0 =========\n",
+                "  01\n",
+                "0 a \n",
+                "\n",
+                "Layer 1 =========\n",
+                "  01\n",
+                "0 #b\n",
+                "\n",
+                "# wall\n"
+            )
+        );
+    }
+}
@@ -1,5 +1,10 @@
 use std::cmp::Reverse;
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::map::{Map, MapCoordinate};
 
 pub trait Neighbors
 where
@@ -8,86 +13,762 @@ where
     fn get_neighbors(&self) -> Vec<(usize, Self)>;
 }
 
+/// Like `Neighbors`, for nodes whose neighbors depend on a context shared by the whole
+/// search, like a map or a grammar. Searches take the context in their `_in` variants.
+pub trait NeighborsIn<Ctx: ?Sized>
+where
+    Self: Sized,
+{
+    fn get_neighbors_in(&self, context: &Ctx) -> Vec<(usize, Self)>;
+}
+
+// self-contained nodes are searched without a context
+impl<T: Neighbors> NeighborsIn<()> for T {
+    fn get_neighbors_in(&self, _: &()) -> Vec<(usize, Self)> {
+        self.get_neighbors()
+    }
+}
+
+/// Coordinates on a map can step to the defined tiles among their neighbors at a cost of 1,
+/// using the neighborhood and topology of the map
+impl<C: MapCoordinate, T> NeighborsIn<Map<C, T>> for C {
+    fn get_neighbors_in(&self, map: &Map<C, T>) -> Vec<(usize, Self)> {
+        map.neighbors(self)
+            .into_iter()
+            .map(|(c, _)| (1, c))
+            .collect()
+    }
+}
+
+/// Queue entry, with the path leading to it in `BFS` or the parent node in `AStar`.
+/// Entries are ordered by distance, then by rank to break ties.
 #[derive(Debug)]
-struct Neighbor<T> {
+struct Neighbor<T, P = Vec<T>> {
     distance: usize,
-    previous: Vec<T>,
+    depth: usize,
+    rank: (i64, u64),
+    previous: P,
     data: T,
 }
 
-impl<T> std::cmp::PartialEq for Neighbor<T> {
+impl<T, P> std::cmp::PartialEq for Neighbor<T, P> {
     fn eq(&self, other: &Self) -> bool {
-        self.distance.eq(&other.distance)
+        self.cmp(other) == std::cmp::Ordering::Equal
     }
 }
 
-impl<T> std::cmp::Eq for Neighbor<T> {}
+impl<T, P> std::cmp::Eq for Neighbor<T, P> {}
 
-impl<T> std::cmp::PartialOrd for Neighbor<T> {
+impl<T, P> std::cmp::PartialOrd for Neighbor<T, P> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.distance.partial_cmp(&other.distance)
+        Some(self.cmp(other))
     }
 }
 
-impl<T> std::cmp::Ord for Neighbor<T> {
+impl<T, P> std::cmp::Ord for Neighbor<T, P> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.distance.cmp(&other.distance)
+        (self.distance, self.rank).cmp(&(other.distance, other.rank))
+    }
+}
+
+type TieKey<'a, T> = Box<dyn Fn(&T) -> i64 + 'a>;
+
+/// Tie-breaking between queue entries of equal distance: by an optional key, smallest
+/// first, and then in insertion order
+struct Order<'a, T> {
+    key: Option<TieKey<'a, T>>,
+    sequence: u64,
+}
+
+impl<'a, T> std::fmt::Debug for Order<'a, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Order")
+            .field("key", &self.key.is_some())
+            .field("sequence", &self.sequence)
+            .finish()
+    }
+}
+
+impl<'a, T> Order<'a, T> {
+    /// Start counting after the start node, which has rank `(0, 0)`
+    fn new() -> Self {
+        Order {
+            key: None,
+            sequence: 1,
+        }
+    }
+
+    /// Get the rank of a node that is about to be queued
+    fn rank(&mut self, node: &T) -> (i64, u64) {
+        let key = self.key.as_ref().map_or(0, |k| k(node));
+        self.sequence += 1;
+        (key, self.sequence - 1)
+    }
+}
+
+/// Bounds on the work done by a search. Paths beyond the maximum cost or depth are not
+/// followed, while running out of expansions or time, or being cancelled, stops the search.
+#[derive(Clone, Default)]
+pub struct Limits {
+    max_cost: Option<usize>,
+    max_depth: Option<usize>,
+    max_expansions: Option<usize>,
+    deadline: Option<Instant>,
+    cancel: Option<Arc<dyn Fn() -> bool + Send + Sync>>,
+}
+
+impl std::fmt::Debug for Limits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Limits")
+            .field("max_cost", &self.max_cost)
+            .field("max_depth", &self.max_depth)
+            .field("max_expansions", &self.max_expansions)
+            .field("deadline", &self.deadline)
+            .field("cancel", &self.cancel.is_some())
+            .finish()
+    }
+}
+
+impl Limits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Do not follow paths longer than a distance
+    pub fn max_cost(mut self, cost: usize) -> Self {
+        self.max_cost = Some(cost);
+        self
+    }
+
+    /// Do not follow paths with more than a number of steps
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Stop after expanding a number of nodes
+    pub fn max_expansions(mut self, expansions: usize) -> Self {
+        self.max_expansions = Some(expansions);
+        self
+    }
+
+    /// Stop once a point in time has passed
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Stop when a function returns true. It is called before expanding every node.
+    pub fn cancel_when<F>(mut self, cancel: F) -> Self
+    where
+        F: Fn() -> bool + Send + Sync + 'static,
+    {
+        self.cancel = Some(Arc::new(cancel));
+        self
+    }
+}
+
+/// Reason for a search to stop before running out of nodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    MaxExpansions,
+    Deadline,
+    Cancelled,
+}
+
+/// Work done by a search
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    /// Number of nodes expanded
+    pub expanded: usize,
+
+    /// Number of entries added to the queue, including the start
+    pub queued: usize,
+
+    /// Largest number of entries in the queue at a time
+    pub peak_frontier: usize,
+}
+
+/// Limits and statistics of a search
+#[derive(Debug)]
+struct Control {
+    limits: Limits,
+    stats: SearchStats,
+    stopped: Option<Stop>,
+}
+
+impl Control {
+    fn new() -> Self {
+        Control {
+            limits: Limits::default(),
+            stats: SearchStats {
+                expanded: 0,
+                queued: 1,
+                peak_frontier: 1,
+            },
+            stopped: None,
+        }
+    }
+
+    /// Check if another node may be expanded, and count it if so
+    fn expand(&mut self) -> bool {
+        if self.stopped.is_none() {
+            let limits = &self.limits;
+            self.stopped = if limits
+                .max_expansions
+                .is_some_and(|m| self.stats.expanded >= m)
+            {
+                Some(Stop::MaxExpansions)
+            } else if limits.deadline.is_some_and(|d| Instant::now() >= d) {
+                Some(Stop::Deadline)
+            } else if limits.cancel.as_ref().is_some_and(|c| c()) {
+                Some(Stop::Cancelled)
+            } else {
+                None
+            };
+        }
+
+        if self.stopped.is_some() {
+            return false;
+        }
+        self.stats.expanded += 1;
+        true
+    }
+
+    /// Check if a path may be followed
+    fn admits(&self, distance: usize, depth: usize) -> bool {
+        self.limits.max_cost.is_none_or(|m| distance <= m)
+            && self.limits.max_depth.is_none_or(|m| depth <= m)
+    }
+
+    /// Count an entry added to a queue, which now has a number of entries
+    fn queued(&mut self, frontier: usize) {
+        self.stats.queued += 1;
+        self.stats.peak_frontier = self.stats.peak_frontier.max(frontier);
     }
 }
 
+/// Search that enumerates all simple paths from the start in order of increasing
+/// distance, yielding the distance, the nodes before the last one and the last node.
+/// Use `Dijkstra` to visit every node only once.
 #[derive(Debug)]
-pub struct BFS<T: Eq> {
+pub struct BFS<'a, T: Eq, Ctx: ?Sized = ()> {
     queue: BinaryHeap<Reverse<Neighbor<T>>>,
+    context: &'a Ctx,
+    control: Control,
+    order: Order<'a, T>,
 }
 
-impl<T: Eq> BFS<T> {
+impl<T: Eq> BFS<'static, T> {
     pub fn new(start: T) -> Self {
+        Self::new_in(start, &())
+    }
+}
+
+impl<'a, T: Eq, Ctx: ?Sized> BFS<'a, T, Ctx> {
+    pub fn new_in(start: T, context: &'a Ctx) -> Self {
         let mut queue = BinaryHeap::new();
         queue.push(Reverse(Neighbor {
             distance: 0,
+            depth: 0,
+            rank: (0, 0),
             previous: Vec::new(),
             data: start,
         }));
 
-        BFS { queue }
+        BFS {
+            queue,
+            context,
+            control: Control::new(),
+            order: Order::new(),
+        }
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.control.limits = limits;
+        self
+    }
+
+    /// Break ties between nodes at equal distance by a key, smallest first. Nodes with
+    /// equal keys, and all nodes by default, are taken in the order they were queued.
+    pub fn with_tie_key<F: Fn(&T) -> i64 + 'a>(mut self, key: F) -> Self {
+        self.order.key = Some(Box::new(key));
+        self
+    }
+
+    /// Get the work done so far
+    pub fn stats(&self) -> SearchStats {
+        self.control.stats
+    }
+
+    /// Get the reason why the search stopped early, if it did
+    pub fn stopped(&self) -> Option<Stop> {
+        self.control.stopped
     }
 }
 
-impl<T> std::iter::Iterator for BFS<T>
+impl<'a, T, Ctx: ?Sized> std::iter::Iterator for BFS<'a, T, Ctx>
 where
-    T: Neighbors + Eq + Clone,
+    T: NeighborsIn<Ctx> + Eq + Clone,
 {
     type Item = (usize, Vec<T>, T);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(i) = self.queue.pop() {
-            let item = i.0;
+        let Reverse(item) = self.queue.pop()?;
+        if !self.control.expand() {
+            self.queue.push(Reverse(item));
+            return None;
+        }
 
-            let mut previous = item.previous.clone();
-            previous.push(item.data.clone());
+        let mut previous = item.previous.clone();
+        previous.push(item.data.clone());
+
+        for (distance, data) in item.data.get_neighbors_in(self.context) {
+            let (distance, depth) = (item.distance + distance, item.depth + 1);
+            if previous.contains(&data) || !self.control.admits(distance, depth) {
+                continue;
+            }
 
-            for (distance, data) in item.data.get_neighbors() {
-                if previous.contains(&data) {
+            self.queue.push(Reverse(Neighbor {
+                distance,
+                depth,
+                previous: previous.clone(),
+                rank: self.order.rank(&data),
+                data,
+            }));
+            self.control.queued(self.queue.len());
+        }
+
+        Some((item.distance, item.previous, item.data))
+    }
+}
+
+/// Search that visits every reachable node once, at its shortest distance from the start,
+/// yielding the distance and the node. Parents of discovered nodes are kept so that paths
+/// can be reconstructed.
+#[derive(Debug)]
+pub struct Dijkstra<'a, T: Eq + Hash, Ctx: ?Sized = ()> {
+    queue: BinaryHeap<Reverse<Neighbor<T, ()>>>,
+
+    /// Best known distance and parent of every discovered node, `None` for the start
+    best: HashMap<T, (usize, Option<T>)>,
+
+    /// Nodes that have been yielded
+    visited: HashSet<T>,
+
+    context: &'a Ctx,
+    control: Control,
+    order: Order<'a, T>,
+}
+
+impl<T: Eq + Hash + Clone> Dijkstra<'static, T> {
+    pub fn new(start: T) -> Self {
+        Self::new_in(start, &())
+    }
+}
+
+impl<'a, T: Eq + Hash + Clone, Ctx: ?Sized> Dijkstra<'a, T, Ctx> {
+    pub fn new_in(start: T, context: &'a Ctx) -> Self {
+        let mut queue = BinaryHeap::new();
+        queue.push(Reverse(Neighbor {
+            distance: 0,
+            depth: 0,
+            rank: (0, 0),
+            previous: (),
+            data: start.clone(),
+        }));
+
+        let mut best = HashMap::new();
+        best.insert(start, (0, None));
+
+        Dijkstra {
+            queue,
+            best,
+            visited: HashSet::new(),
+            context,
+            control: Control::new(),
+            order: Order::new(),
+        }
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.control.limits = limits;
+        self
+    }
+
+    /// Break ties between nodes at equal distance by a key, smallest first. Nodes with
+    /// equal keys, and all nodes by default, are taken in the order they were queued.
+    pub fn with_tie_key<F: Fn(&T) -> i64 + 'a>(mut self, key: F) -> Self {
+        self.order.key = Some(Box::new(key));
+        self
+    }
+
+    /// Get the work done so far
+    pub fn stats(&self) -> SearchStats {
+        self.control.stats
+    }
+
+    /// Get the reason why the search stopped early, if it did
+    pub fn stopped(&self) -> Option<Stop> {
+        self.control.stopped
+    }
+
+    /// Check if a node has been yielded already
+    pub fn is_visited(&self, node: &T) -> bool {
+        self.visited.contains(node)
+    }
+
+    /// Get the shortest distance to a visited node
+    pub fn distance(&self, node: &T) -> Option<usize> {
+        if self.is_visited(node) {
+            self.best.get(node).map(|(d, _)| *d)
+        } else {
+            None
+        }
+    }
+
+    /// Get a shortest path from the start to a visited node, including both ends
+    pub fn path(&self, node: &T) -> Option<Vec<T>> {
+        if self.is_visited(node) {
+            Some(self.trace(node))
+        } else {
+            None
+        }
+    }
+
+    /// Follow the parents from a discovered node back to the start
+    fn trace(&self, node: &T) -> Vec<T> {
+        let mut path = vec![node.clone()];
+        let mut current = &self.best[node].1;
+        while let Some(parent) = current {
+            path.push(parent.clone());
+            current = &self.best[parent].1;
+        }
+
+        path.reverse();
+        path
+    }
+
+    /// Get a lower bound for the distance of the next node to be yielded
+    fn frontier(&self) -> Option<usize> {
+        self.queue.peek().map(|Reverse(n)| n.distance)
+    }
+}
+
+impl<'a, T, Ctx: ?Sized> std::iter::Iterator for Dijkstra<'a, T, Ctx>
+where
+    T: NeighborsIn<Ctx> + Eq + Hash + Clone,
+{
+    type Item = (usize, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(Reverse(item)) = self.queue.pop() {
+            // stale entries were superseded by a shorter path to the same node
+            if self.is_visited(&item.data) {
+                continue;
+            }
+            if !self.control.expand() {
+                self.queue.push(Reverse(item));
+                return None;
+            }
+
+            for (distance, data) in item.data.get_neighbors_in(self.context) {
+                let (distance, depth) = (item.distance + distance, item.depth + 1);
+                if self.is_visited(&data)
+                    || self.best.get(&data).is_some_and(|b| b.0 <= distance)
+                    || !self.control.admits(distance, depth)
+                {
                     continue;
                 }
 
+                self.best
+                    .insert(data.clone(), (distance, Some(item.data.clone())));
                 self.queue.push(Reverse(Neighbor {
-                    distance: item.distance + distance,
-                    previous: previous.clone(),
+                    distance,
+                    depth,
+                    previous: (),
+                    rank: self.order.rank(&data),
                     data,
-                }))
+                }));
+                self.control.queued(self.queue.len());
             }
 
-            Some((item.distance, item.previous, item.data))
+            self.visited.insert(item.data.clone());
+            return Some((item.distance, item.data));
+        }
+
+        None
+    }
+}
+
+/// Find a shortest path from `start` to a node matching a goal, getting its distance and
+/// the path, including both ends
+pub fn shortest_path<T, G>(start: T, is_goal: G) -> Option<(usize, Vec<T>)>
+where
+    T: Neighbors + Eq + Hash + Clone,
+    G: Fn(&T) -> bool,
+{
+    shortest_path_in(start, &(), is_goal)
+}
+
+/// Find a shortest path like `shortest_path`, within a context
+pub fn shortest_path_in<T, Ctx, G>(start: T, context: &Ctx, is_goal: G) -> Option<(usize, Vec<T>)>
+where
+    T: NeighborsIn<Ctx> + Eq + Hash + Clone,
+    Ctx: ?Sized,
+    G: Fn(&T) -> bool,
+{
+    let mut search = Dijkstra::new_in(start, context);
+    let (distance, node) = search.by_ref().find(|(_, n)| is_goal(n))?;
+    Some((distance, search.trace(&node)))
+}
+
+/// Get all nodes reachable from `start`, including itself
+pub fn reachable_from<T>(start: T) -> HashSet<T>
+where
+    T: Neighbors + Eq + Hash + Clone,
+{
+    reachable_from_in(start, &())
+}
+
+/// Get all nodes reachable from `start` within a context, including itself
+pub fn reachable_from_in<T, Ctx>(start: T, context: &Ctx) -> HashSet<T>
+where
+    T: NeighborsIn<Ctx> + Eq + Hash + Clone,
+    Ctx: ?Sized,
+{
+    Dijkstra::new_in(start, context).map(|(_, n)| n).collect()
+}
+
+/// Get the shortest distances from `start` to all nodes within a maximum distance
+pub fn distance_map<T>(start: T, max_cost: usize) -> HashMap<T, usize>
+where
+    T: Neighbors + Eq + Hash + Clone,
+{
+    distance_map_in(start, &(), max_cost)
+}
+
+/// Get the shortest distances like `distance_map`, within a context
+pub fn distance_map_in<T, Ctx>(start: T, context: &Ctx, max_cost: usize) -> HashMap<T, usize>
+where
+    T: NeighborsIn<Ctx> + Eq + Hash + Clone,
+    Ctx: ?Sized,
+{
+    Dijkstra::new_in(start, context)
+        .with_limits(Limits::new().max_cost(max_cost))
+        .map(|(d, n)| (n, d))
+        .collect()
+}
+
+/// Find a shortest path between two nodes by searching from both ends until the searches
+/// meet, getting its distance and the path, including both ends. The graph must be
+/// reversible: for every edge, there must be an edge of the same cost in the opposite
+/// direction.
+pub fn bidirectional_shortest_path<T>(start: T, goal: T) -> Option<(usize, Vec<T>)>
+where
+    T: Neighbors + Eq + Hash + Clone,
+{
+    bidirectional_shortest_path_in(start, goal, &())
+}
+
+/// Find a shortest path like `bidirectional_shortest_path`, within a context
+pub fn bidirectional_shortest_path_in<T, Ctx>(
+    start: T,
+    goal: T,
+    context: &Ctx,
+) -> Option<(usize, Vec<T>)>
+where
+    T: NeighborsIn<Ctx> + Eq + Hash + Clone,
+    Ctx: ?Sized,
+{
+    let mut forward = Dijkstra::new_in(start, context);
+    let mut backward = Dijkstra::new_in(goal, context);
+    let mut meeting: Option<(usize, T)> = None;
+
+    loop {
+        // advance the search with the closer frontier, until no path through the
+        // unvisited nodes can be shorter than the best one found
+        let (f, b) = (forward.frontier(), backward.frontier());
+        let forward_first = match (f, b) {
+            (None, None) => break,
+            (Some(f), Some(b)) => f <= b,
+            (f, _) => f.is_some(),
+        };
+        let bound = f.unwrap_or(0) + b.unwrap_or(0);
+        if meeting.as_ref().is_some_and(|(d, _)| bound >= *d) {
+            break;
+        }
+
+        let (search, other) = if forward_first {
+            (&mut forward, &backward)
         } else {
-            None
+            (&mut backward, &forward)
+        };
+        if let Some((d, node)) = search.next() {
+            if let Some((o, _)) = other.best.get(&node) {
+                if meeting.as_ref().is_none_or(|(m, _)| d + o < *m) {
+                    meeting = Some((d + o, node));
+                }
+            }
         }
     }
+
+    let (distance, node) = meeting?;
+    let mut path = forward.trace(&node);
+    let mut rest = backward.trace(&node);
+    rest.pop();
+    path.extend(rest.into_iter().rev());
+    Some((distance, path))
+}
+
+/// A* search towards a goal, guided by a heuristic that estimates the remaining distance
+/// from a node to the goal. Found paths are shortest paths as long as the heuristic never
+/// overestimates the distance.
+#[derive(Debug)]
+pub struct AStar<'a, T: Eq + Hash, H, Ctx: ?Sized = ()> {
+    /// Entries are ordered by the distance so far plus the estimated remaining distance
+    queue: BinaryHeap<Reverse<Neighbor<T, Option<T>>>>,
+
+    /// Best known distance to every discovered node
+    best: HashMap<T, usize>,
+
+    /// Parent of every expanded node, `None` for the start
+    parents: HashMap<T, Option<T>>,
+
+    heuristic: H,
+    context: &'a Ctx,
+    control: Control,
+    order: Order<'a, T>,
+}
+
+impl<T, H> AStar<'static, T, H>
+where
+    T: Neighbors + Eq + Hash + Clone,
+    H: Fn(&T) -> usize,
+{
+    pub fn new(start: T, heuristic: H) -> Self {
+        Self::new_in(start, &(), heuristic)
+    }
+}
+
+impl<'a, T, H, Ctx> AStar<'a, T, H, Ctx>
+where
+    T: NeighborsIn<Ctx> + Eq + Hash + Clone,
+    H: Fn(&T) -> usize,
+    Ctx: ?Sized,
+{
+    pub fn new_in(start: T, context: &'a Ctx, heuristic: H) -> Self {
+        let mut queue = BinaryHeap::new();
+        queue.push(Reverse(Neighbor {
+            distance: heuristic(&start),
+            depth: 0,
+            rank: (0, 0),
+            previous: None,
+            data: start.clone(),
+        }));
+
+        let mut best = HashMap::new();
+        best.insert(start, 0);
+
+        AStar {
+            queue,
+            best,
+            parents: HashMap::new(),
+            heuristic,
+            context,
+            control: Control::new(),
+            order: Order::new(),
+        }
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.control.limits = limits;
+        self
+    }
+
+    /// Break ties between nodes at equal distance by a key, smallest first. Nodes with
+    /// equal keys, and all nodes by default, are taken in the order they were queued.
+    pub fn with_tie_key<F: Fn(&T) -> i64 + 'a>(mut self, key: F) -> Self {
+        self.order.key = Some(Box::new(key));
+        self
+    }
+
+    /// Get the work done so far
+    pub fn stats(&self) -> SearchStats {
+        self.control.stats
+    }
+
+    /// Get the reason why the search stopped early, if it did
+    pub fn stopped(&self) -> Option<Stop> {
+        self.control.stopped
+    }
+
+    /// Get the number of nodes expanded so far. Nodes are expanded again when a shorter
+    /// path to them is found, which only happens with inconsistent heuristics.
+    pub fn expanded(&self) -> usize {
+        self.control.stats.expanded
+    }
+
+    /// Search until a node matching the goal is expanded, getting its distance and the
+    /// path to it, including both ends
+    pub fn find<G: Fn(&T) -> bool>(&mut self, is_goal: G) -> Option<(usize, Vec<T>)> {
+        while let Some(Reverse(item)) = self.queue.pop() {
+            let distance = item.distance - (self.heuristic)(&item.data);
+            if self.best[&item.data] < distance {
+                continue;
+            }
+            if !self.control.expand() {
+                self.queue.push(Reverse(item));
+                return None;
+            }
+
+            self.parents.insert(item.data.clone(), item.previous);
+            if is_goal(&item.data) {
+                let path = self.path(&item.data);
+                return Some((distance, path));
+            }
+
+            for (step, data) in item.data.get_neighbors_in(self.context) {
+                let (next, depth) = (distance + step, item.depth + 1);
+                if self.best.get(&data).is_some_and(|d| *d <= next)
+                    || !self.control.admits(next, depth)
+                {
+                    continue;
+                }
+
+                self.best.insert(data.clone(), next);
+                self.queue.push(Reverse(Neighbor {
+                    distance: next + (self.heuristic)(&data),
+                    depth,
+                    previous: Some(item.data.clone()),
+                    rank: self.order.rank(&data),
+                    data,
+                }));
+                self.control.queued(self.queue.len());
+            }
+        }
+
+        None
+    }
+
+    fn path(&self, node: &T) -> Vec<T> {
+        let mut path = vec![node.clone()];
+        let mut current = &self.parents[node];
+        while let Some(parent) = current {
+            path.push(parent.clone());
+            current = &self.parents[parent];
+        }
+
+        path.reverse();
+        path
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::map::{MapReader, Neighborhood, Topology};
+    use std::sync::atomic::{AtomicBool, Ordering};
 
     #[derive(Debug, PartialEq, Eq, Clone)]
     struct Word(String);
@@ -131,39 +812,354 @@ mod tests {
          *                   |-------------------------D xcccccc
          *
          */
+        // nodes at equal distance come out in the order they were queued
         let expected = vec![
             (0, "x"),
             (1, "xa"),
             (2, "xbb"),
             (2, "xaa"),
             (3, "xccc"),
-            (3, "xaaa"),
-            (3, "xbba"),
             (3, "xabb"),
+            (3, "xbba"),
+            (3, "xaaa"),
             (4, "xaccc"),
             (4, "xbbbb"),
+            (4, "xaabb"),
+            (4, "xccca"),
             (4, "xabba"),
             (4, "xbbaa"),
             (4, "xaaaa"),
-            (4, "xccca"),
-            (4, "xaabb"),
+            (5, "xbbccc"),
             (5, "xaaccc"),
+            (5, "xcccbb"),
+            (5, "xabbbb"),
             (5, "xbbabb"),
             (5, "xaaabb"),
-            (5, "xabbbb"),
-            (5, "xbbccc"),
-            (5, "xcccbb"),
-            (6, "xaaaccc"),
-            (6, "xbbaccc"),
-            (6, "xabbccc"),
             (6, "xcccccc"),
+            (6, "xabbccc"),
+            (6, "xbbaccc"),
+            (6, "xaaaccc"),
         ];
 
         assert_eq!(out.len(), expected.len());
-        for (i, ((da, _, wa), (db, wb))) in out.iter().zip(expected.iter()).enumerate() {
-            dbg!(i, da, wa, db, wb);
+        for ((da, _, wa), (db, wb)) in out.iter().zip(expected.iter()) {
             assert_eq!(da, db);
             assert_eq!(wa.0, *wb);
         }
     }
+
+    #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+    struct Grid(u8, u8);
+
+    impl Neighbors for Grid {
+        fn get_neighbors(&self) -> Vec<(usize, Self)> {
+            let mut out = Vec::new();
+            if self.0 < 9 {
+                out.push((1, Grid(self.0 + 1, self.1)));
+            }
+            if self.1 < 9 {
+                out.push((1, Grid(self.0, self.1 + 1)));
+            }
+            out
+        }
+    }
+
+    #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+    struct Town(char);
+
+    impl Neighbors for Town {
+        fn get_neighbors(&self) -> Vec<(usize, Self)> {
+            let roads: &[(usize, char)] = match self.0 {
+                'a' => &[(7, 'b'), (2, 'c'), (9, 'e')],
+                'b' => &[(7, 'a'), (1, 'd')],
+                'c' => &[(2, 'a'), (3, 'b'), (8, 'd')],
+                'd' => &[(1, 'b'), (8, 'c'), (2, 'e')],
+                _ => &[],
+            };
+            roads.iter().map(|(d, t)| (*d, Town(*t))).collect()
+        }
+    }
+
+    #[test]
+    fn test_dijkstra() {
+        // every node of the grid is reached by many paths, but visited only once
+        let mut search = Dijkstra::new(Grid(0, 0));
+        let out: Vec<_> = search.by_ref().collect();
+        assert_eq!(out.len(), 100);
+        assert!(out.iter().all(|(d, g)| *d == (g.0 + g.1) as usize));
+        assert!(out.windows(2).all(|w| w[0].0 <= w[1].0));
+
+        let path = search.path(&Grid(9, 9)).unwrap();
+        assert_eq!(path.len(), 19);
+        assert_eq!(path[0], Grid(0, 0));
+        assert_eq!(search.distance(&Grid(9, 9)), Some(18));
+
+        let mut search = Dijkstra::new(Town('a'));
+        let out: Vec<_> = search.by_ref().map(|(d, t)| (d, t.0)).collect();
+        assert_eq!(out, vec![(0, 'a'), (2, 'c'), (5, 'b'), (6, 'd'), (8, 'e')]);
+
+        let path: String = search
+            .path(&Town('e'))
+            .unwrap()
+            .iter()
+            .map(|t| t.0)
+            .collect();
+        assert_eq!(path, "acbde");
+        assert_eq!(search.path(&Town('f')), None);
+        assert!(!search.is_visited(&Town('f')));
+    }
+
+    #[rustfmt::skip]
+    const MAZE: &[&str] = &[
+        "......",
+        ".####.",
+        ".#....",
+        ".#.##.",
+        "...#..",
+        ".#....",
+    ];
+
+    #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+    struct Cell(usize, usize);
+
+    impl Neighbors for Cell {
+        fn get_neighbors(&self) -> Vec<(usize, Self)> {
+            let open = |i: usize, j: usize| {
+                MAZE.get(i).and_then(|row| row.as_bytes().get(j)) == Some(&b'.')
+            };
+
+            let Cell(i, j) = *self;
+            if !open(i, j) {
+                return Vec::new();
+            }
+
+            [
+                (i + 1, j),
+                (i, j + 1),
+                (i.wrapping_sub(1), j),
+                (i, j.wrapping_sub(1)),
+            ]
+            .iter()
+            .filter(|(i, j)| open(*i, *j))
+            .map(|(i, j)| (1, Cell(*i, *j)))
+            .collect()
+        }
+    }
+
+    #[test]
+    fn test_astar() {
+        let goal = Cell(5, 5);
+        let manhattan = |c: &Cell| (5 - c.0) + (5 - c.1);
+
+        let mut astar = AStar::new(Cell(0, 0), manhattan);
+        let (distance, path) = astar.find(|c| *c == goal).unwrap();
+        assert_eq!(distance, 10);
+        assert_eq!(path.len(), 11);
+        assert_eq!((path[0], path[10]), (Cell(0, 0), goal));
+        assert!(path
+            .windows(2)
+            .all(|w| w[0].get_neighbors().iter().any(|(_, n)| *n == w[1])));
+
+        // without a heuristic, A* still finds a shortest path but expands more nodes
+        let mut uninformed = AStar::new(Cell(0, 0), |_| 0);
+        assert_eq!(uninformed.find(|c| *c == goal).map(|(d, _)| d), Some(10));
+        let dijkstra = Dijkstra::new(Cell(0, 0))
+            .position(|(_, c)| c == goal)
+            .unwrap()
+            + 1;
+        let bfs = BFS::new(Cell(0, 0))
+            .position(|(_, _, c)| c == goal)
+            .unwrap()
+            + 1;
+        assert!(astar.expanded() < uninformed.expanded());
+        assert!(astar.expanded() < dijkstra);
+        assert!(dijkstra < bfs);
+
+        let mut unreachable = AStar::new(Cell(0, 0), manhattan);
+        assert_eq!(unreachable.find(|c| *c == Cell(1, 1)), None);
+        assert_eq!(unreachable.expanded(), 26);
+    }
+
+    #[test]
+    fn test_astar_on_map() {
+        let mut map: Map<[i64; 2], char> = MapReader::new()
+            .void_chars("#")
+            .parse(concat!(
+                "........\n",
+                "........\n",
+                "######..\n",
+                ".....#..\n",
+                ".....#..\n",
+                "........",
+            ))
+            .unwrap();
+        map.set_neighborhood(Neighborhood::VonNeumann(1));
+
+        // the wall forces a detour, so the heuristic underestimates the distance
+        let goal = [4, 2];
+        let manhattan = |[i, j]: &[i64; 2]| ((goal[0] - i).abs() + (goal[1] - j).abs()) as usize;
+        let mut astar = AStar::new_in([0, 0], &map, manhattan);
+        let (distance, path) = astar.find(|c| *c == goal).unwrap();
+        assert_eq!(distance, 16);
+        assert_eq!(path.len(), 17);
+        assert!(path
+            .windows(2)
+            .all(|w| map.neighbors(&w[0]).iter().any(|(c, _)| *c == w[1])));
+
+        let mut bfs = BFS::new_in([0, 0], &map);
+        assert_eq!(
+            bfs.find(|(_, _, c)| *c == goal).map(|(d, _, _)| d),
+            Some(16)
+        );
+        assert!(astar.expanded() < map.len());
+        assert!(astar.expanded() < bfs.stats().expanded);
+    }
+
+    #[test]
+    fn test_goal_directed() {
+        let (distance, path) = shortest_path(Town('a'), |t| *t == Town('e')).unwrap();
+        assert_eq!(distance, 8);
+        assert_eq!(path.iter().map(|t| t.0).collect::<String>(), "acbde");
+        assert_eq!(shortest_path(Town('e'), |t| *t == Town('a')), None);
+
+        assert_eq!(reachable_from(Town('b')).len(), 5);
+        assert_eq!(
+            reachable_from(Town('e')),
+            vec![Town('e')].into_iter().collect()
+        );
+
+        let distances = distance_map(Grid(0, 0), 2);
+        assert_eq!(distances.len(), 6);
+        assert_eq!(distances[&Grid(1, 1)], 2);
+
+        // the maze is reversible, so searching from both ends finds the same distances
+        for (cell, distance) in distance_map(Cell(0, 0), usize::MAX) {
+            let (d, path) = bidirectional_shortest_path(Cell(0, 0), cell).unwrap();
+            assert_eq!(d, distance);
+            assert_eq!(path.len(), distance + 1);
+            assert_eq!((path[0], path[distance]), (Cell(0, 0), cell));
+            assert!(path
+                .windows(2)
+                .all(|w| w[0].get_neighbors().iter().any(|(_, n)| *n == w[1])));
+        }
+        assert_eq!(bidirectional_shortest_path(Cell(0, 0), Cell(1, 1)), None);
+    }
+
+    #[test]
+    fn test_context() {
+        let mut map: Map<[i32; 2], char> = MapReader::new()
+            .void_chars("#")
+            .parse(&MAZE.join("\n"))
+            .unwrap();
+        map.set_neighborhood(Neighborhood::VonNeumann(1));
+
+        let goal = [5, 5];
+        let (distance, path) = shortest_path_in([0, 0], &map, |c| *c == goal).unwrap();
+        assert_eq!(distance, 10);
+        assert!(path.iter().all(|c| map.get(c) == Some(&'.')));
+
+        let manhattan = |[i, j]: &[i32; 2]| ((goal[0] - i).abs() + (goal[1] - j).abs()) as usize;
+        let found = AStar::new_in([0, 0], &map, manhattan).find(|c| *c == goal);
+        assert_eq!(found.map(|(d, _)| d), Some(10));
+        assert_eq!(reachable_from_in([0, 0], &map).len(), map.len());
+        assert_eq!(distance_map_in([0, 0], &map, 1).len(), 3);
+
+        // on a cylinder, the goal is a step away from the left edge
+        map.set_topology(&[Topology::Bounded, Topology::Wrap]);
+        let bidirectional = bidirectional_shortest_path_in([0, 0], goal, &map);
+        assert_eq!(bidirectional.map(|(d, _)| d), Some(6));
+        assert_eq!(
+            BFS::new_in([0, 0], &map)
+                .find(|(_, _, c)| *c == goal)
+                .unwrap()
+                .0,
+            6
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut bfs = BFS::new(Word("x".to_string())).with_limits(Limits::new().max_depth(2));
+        assert_eq!(bfs.by_ref().count(), 13);
+        assert_eq!(bfs.stopped(), None);
+        assert_eq!(
+            bfs.stats(),
+            SearchStats {
+                expanded: 13,
+                queued: 13,
+                peak_frontier: 8
+            }
+        );
+
+        let near = Dijkstra::new(Grid(0, 0)).with_limits(Limits::new().max_cost(3));
+        assert!(near.map(|(d, _)| d).eq(vec![0, 1, 1, 2, 2, 2, 3, 3, 3, 3]));
+
+        let mut few = Dijkstra::new(Grid(0, 0)).with_limits(Limits::new().max_expansions(5));
+        assert_eq!(few.by_ref().count(), 5);
+        assert_eq!(few.next(), None);
+        assert_eq!(few.stopped(), Some(Stop::MaxExpansions));
+        assert_eq!(few.stats().expanded, 5);
+
+        let mut late =
+            Dijkstra::new(Grid(0, 0)).with_limits(Limits::new().deadline(Instant::now()));
+        assert_eq!(late.next(), None);
+        assert_eq!(late.stopped(), Some(Stop::Deadline));
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let flag = cancelled.clone();
+        let mut search = BFS::new(Cell(0, 0))
+            .with_limits(Limits::new().cancel_when(move || flag.load(Ordering::Relaxed)));
+        assert_eq!(search.by_ref().take(3).count(), 3);
+        cancelled.store(true, Ordering::Relaxed);
+        assert_eq!(search.next(), None);
+        assert_eq!(search.stopped(), Some(Stop::Cancelled));
+
+        let mut astar = AStar::new(Cell(0, 0), |_| 0).with_limits(Limits::new().max_expansions(3));
+        assert_eq!(astar.find(|c| *c == Cell(5, 5)), None);
+        assert_eq!(astar.stopped(), Some(Stop::MaxExpansions));
+        assert_eq!(astar.expanded(), 3);
+
+        let mut full = Dijkstra::new(Grid(0, 0));
+        assert_eq!(full.by_ref().count(), 100);
+        assert_eq!(full.stats().queued, 100);
+        assert_eq!(full.stopped(), None);
+    }
+
+    #[test]
+    fn test_tie_breaking() {
+        let fifo: Vec<_> = Dijkstra::new(Grid(0, 0)).take(6).map(|(_, g)| g).collect();
+        assert_eq!(
+            fifo,
+            vec![
+                Grid(0, 0),
+                Grid(1, 0),
+                Grid(0, 1),
+                Grid(2, 0),
+                Grid(1, 1),
+                Grid(0, 2)
+            ]
+        );
+
+        let keyed: Vec<_> = Dijkstra::new(Grid(0, 0))
+            .with_tie_key(|g| -(g.1 as i64))
+            .take(6)
+            .map(|(_, g)| g)
+            .collect();
+        assert_eq!(
+            keyed,
+            vec![
+                Grid(0, 0),
+                Grid(0, 1),
+                Grid(1, 0),
+                Grid(0, 2),
+                Grid(1, 1),
+                Grid(2, 0)
+            ]
+        );
+
+        // the search is reproducible
+        let first: Vec<_> = BFS::new(Cell(0, 0)).take(50).collect();
+        let second: Vec<_> = BFS::new(Cell(0, 0)).take(50).collect();
+        assert_eq!(first, second);
+    }
 }
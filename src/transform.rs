@@ -0,0 +1,244 @@
+use crate::map::{Bounds, IntCoord, Map, MapTile};
+
+/// One of the eight symmetries of a square (the dihedral group D4), expressed as an
+/// optional flip along axis 0 followed by a number of right rotations.
+///
+/// Transforms can be composed with `+`, where `a + b` applies `a` first and `b` second,
+/// and inverted with `-`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Transform {
+    flip: bool,
+    rotate: u8,
+}
+
+impl Transform {
+    /// Create a transform that first optionally flips along axis 0 and then rotates
+    /// right `rotate` times
+    pub fn new(flip: bool, rotate: u8) -> Self {
+        Transform {
+            flip,
+            rotate: rotate % 4,
+        }
+    }
+
+    /// The transform that leaves everything in place
+    pub fn identity() -> Self {
+        Transform::default()
+    }
+
+    /// Rotate right a number of times
+    pub fn rotation(rotate: u8) -> Self {
+        Transform::new(false, rotate)
+    }
+
+    /// Mirror along an axis, i.e. reverse the order of coordinates along that axis
+    pub fn mirror(axis: usize) -> Self {
+        match axis {
+            0 => Transform::new(true, 0),
+            1 => Transform::new(true, 2),
+            _ => panic!("Invalid axis: {}", axis),
+        }
+    }
+
+    /// All eight transforms
+    pub fn all() -> [Transform; 8] {
+        let mut out = [Transform::identity(); 8];
+        for (i, t) in out.iter_mut().enumerate() {
+            *t = Transform::new(i >= 4, (i % 4) as u8);
+        }
+        out
+    }
+
+    /// Check if the transform includes a flip
+    pub fn is_flipped(&self) -> bool {
+        self.flip
+    }
+
+    /// Get the number of right rotations applied after the flip
+    pub fn rotations(&self) -> u8 {
+        self.rotate
+    }
+
+    /// Get the extent that a transformed extent will have.
+    /// The minimum corner stays in place.
+    pub fn apply_to_extent<I: IntCoord>(&self, bounds: Bounds<[I; 2]>) -> Bounds<[I; 2]> {
+        let Bounds { min, max } = bounds;
+        match self.rotate {
+            1 | 3 => Bounds::new(
+                min,
+                [min[0] + (max[1] - min[1]), min[1] + (max[0] - min[0])],
+            ),
+            _ => bounds,
+        }
+    }
+
+    /// Transform a coordinate within an extent
+    pub fn apply_to_coord<I: IntCoord>(&self, pos: [I; 2], bounds: Bounds<[I; 2]>) -> [I; 2] {
+        let [mut i, mut j] = pos;
+        let Bounds { mut min, mut max } = bounds;
+
+        if self.flip {
+            i = min[0] + (max[0] - i);
+        }
+
+        //  j 01234    0123
+        // i
+        // 0  abcde    kfa
+        // 1  fghIj    Lgb
+        // 2  kLmno    mhc
+        // 3           nId
+        // 4           oje
+        for _ in 0..self.rotate {
+            let rotated_bounds = Transform::rotation(1).apply_to_extent(Bounds::new(min, max));
+            let rotated = [min[0] + (j - min[1]), min[1] + (max[0] - i)];
+            i = rotated[0];
+            j = rotated[1];
+            min = rotated_bounds.min;
+            max = rotated_bounds.max;
+        }
+
+        [i, j]
+    }
+}
+
+impl std::ops::Add for Transform {
+    type Output = Transform;
+
+    /// Compose two transforms, applying `self` first and `rhs` second
+    fn add(self, rhs: Self) -> Self::Output {
+        // a flip turns preceding right rotations into left rotations
+        let rotate = if rhs.flip {
+            4 - self.rotate
+        } else {
+            self.rotate
+        };
+
+        Transform::new(self.flip != rhs.flip, rotate + rhs.rotate)
+    }
+}
+
+impl std::ops::Neg for Transform {
+    type Output = Transform;
+
+    fn neg(self) -> Self::Output {
+        if self.flip {
+            // flips followed by rotations are their own inverse
+            self
+        } else {
+            Transform::rotation(4 - self.rotate)
+        }
+    }
+}
+
+impl std::ops::Sub for Transform {
+    type Output = Transform;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self + (-rhs)
+    }
+}
+
+impl<T, I> Map<[I; 2], T>
+where
+    T: MapTile,
+    I: IntCoord,
+{
+    /// Apply a transform to the map. The transformed map keeps the minimum corner of
+    /// its extent in place.
+    pub fn transform(&self, transform: Transform) -> Self {
+        let bounds = self.bounds();
+        let new_bounds = transform.apply_to_extent(bounds);
+
+        let mut out = if self.is_dense() {
+            Map::new_dense(new_bounds)
+        } else {
+            Map::new()
+        };
+        out.set_neighborhood(self.neighborhood());
+        out.fixed_extent = self.fixed_extent.map(|_| new_bounds);
+
+        for (pos, tile) in self.iter() {
+            out.set(transform.apply_to_coord(pos, bounds), tile.clone());
+        }
+
+        out
+    }
+
+    /// Get the map in all eight orientations, along with the transforms that produce them
+    pub fn all_orientations(&self) -> Vec<(Transform, Self)> {
+        Transform::all()
+            .iter()
+            .map(|t| (*t, self.transform(*t)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_addition() {
+        let map: Map<[usize; 2], char> = "abc\ndef\nghi".parse().unwrap();
+
+        for a in Transform::all().iter() {
+            for b in Transform::all().iter() {
+                assert_eq!(
+                    map.transform(*a).transform(*b),
+                    map.transform(*a + *b),
+                    "{:?} + {:?}",
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_neutral() {
+        for a in Transform::all().iter() {
+            assert_eq!(*a + (-*a), Transform::identity());
+            assert_eq!(*a - *a, Transform::identity());
+        }
+    }
+
+    #[test]
+    fn test_all_distinct() {
+        let map: Map<[usize; 2], char> = "abc\ndef".parse().unwrap();
+        let orientations = map.all_orientations();
+        assert_eq!(orientations.len(), 8);
+
+        for (i, (_, a)) in orientations.iter().enumerate() {
+            for (_, b) in &orientations[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_negative_extent() {
+        let mut map: Map<[i64; 2], char> = Map::new();
+        map.set([-3, -2], 'a');
+        map.set([-3, 0], 'b');
+        map.set([-2, -2], 'c');
+
+        // original  rotated  mirrored
+        //   a.b       ca       b.a
+        //   c..       ..       ..c
+        //             .b
+        let rotated = map.transform(Transform::rotation(1));
+        assert_eq!(rotated.get_extent(), ([-3, -2], [-1, -1]));
+        assert_eq!(rotated.get(&[-3, -2]), Some(&'c'));
+        assert_eq!(rotated.get(&[-3, -1]), Some(&'a'));
+        assert_eq!(rotated.get(&[-1, -1]), Some(&'b'));
+
+        let mirrored = map.transform(Transform::mirror(1));
+        assert_eq!(mirrored.get_extent(), ([-3, -2], [-2, 0]));
+        assert_eq!(mirrored.get(&[-3, -2]), Some(&'b'));
+        assert_eq!(mirrored.get(&[-2, 0]), Some(&'c'));
+
+        for (t, m) in map.all_orientations() {
+            assert_eq!(m.transform(-t), map, "{:?}", t);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crate::map::{Bounds, IntCoord, Map};
+
+/// A borrowed part of a map, with coordinates relative to the minimum corner of the part
+pub struct MapView<'a, I: IntCoord, T, const N: usize> {
+    map: &'a Map<[I; N], T>,
+
+    /// Bounds of the view in coordinates of the underlying map
+    source: Bounds<[I; N]>,
+}
+
+impl<T, I, const N: usize> Map<[I; N], T>
+where
+    I: IntCoord,
+{
+    /// Get a view of the part of the map within some bounds
+    pub fn view(&self, bounds: Bounds<[I; N]>) -> MapView<'_, I, T, N> {
+        MapView {
+            map: self,
+            source: bounds,
+        }
+    }
+}
+
+impl<'a, I: IntCoord, T, const N: usize> MapView<'a, I, T, N> {
+    /// Translate a view coordinate into a coordinate of the underlying map
+    fn to_source(&self, pos: &[I; N]) -> Option<[I; N]> {
+        let mut out = *pos;
+        for (o, m) in out.iter_mut().zip(self.source.min.iter()) {
+            *o = o.checked_add(m)?;
+        }
+
+        if self.source.contains(&out) {
+            Some(out)
+        } else {
+            None
+        }
+    }
+
+    fn to_view(&self, pos: &[I; N]) -> [I; N] {
+        let mut out = *pos;
+        for (o, m) in out.iter_mut().zip(self.source.min.iter()) {
+            *o = *o - *m;
+        }
+        out
+    }
+
+    /// Get the bounds of the view, starting at zero along every axis
+    pub fn bounds(&self) -> Bounds<[I; N]> {
+        Bounds::new([I::zero(); N], self.to_view(&self.source.max))
+    }
+
+    /// Get the tile at a view coordinate
+    pub fn get(&self, pos: &[I; N]) -> Option<&'a T> {
+        self.map.get(&self.to_source(pos)?)
+    }
+
+    /// Iterate over the view coordinates and tiles of all defined tiles in the view
+    pub fn iter(&self) -> Box<dyn Iterator<Item = ([I; N], &'a T)> + '_> {
+        if self.source.count() < self.map.len() {
+            Box::new(
+                self.source
+                    .iter()
+                    .filter_map(move |c| self.map.get(&c).map(|t| (self.to_view(&c), t))),
+            )
+        } else {
+            Box::new(
+                self.map
+                    .iter()
+                    .filter(move |(c, _)| self.source.contains(c))
+                    .map(move |(c, t)| (self.to_view(&c), t)),
+            )
+        }
+    }
+
+    /// Get a view of a part of this view, in view coordinates
+    pub fn view(&self, bounds: Bounds<[I; N]>) -> Option<MapView<'a, I, T, N>> {
+        let min = self.to_source(&bounds.min)?;
+        let max = self.to_source(&bounds.max)?;
+
+        Some(MapView {
+            map: self.map,
+            source: Bounds::new(min, max),
+        })
+    }
+}
+
+impl<'a, I: IntCoord, T: Clone, const N: usize> MapView<'a, I, T, N> {
+    /// Copy the tiles of the view into a new map, using view coordinates
+    pub fn to_map(&self) -> Map<[I; N], T> {
+        let mut out = Map::new();
+        out.set_neighborhood(self.map.neighborhood());
+        for (pos, t) in self.iter() {
+            out.set(pos, t.clone());
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_view() {
+        let map: Map<[u8; 2], char> = "abcd\nefgh\nijkl".parse().unwrap();
+
+        let view = map.view(Bounds::new([1, 1], [2, 3]));
+        assert_eq!(view.bounds(), Bounds::new([0, 0], [1, 2]));
+        assert_eq!(view.get(&[0, 0]), Some(&'f'));
+        assert_eq!(view.get(&[1, 2]), Some(&'l'));
+        assert_eq!(view.get(&[2, 0]), None);
+        assert_eq!(view.get(&[255, 0]), None);
+        assert_eq!(view.to_map().to_string(), "fgh\njkl\n");
+        assert_eq!(view.iter().count(), 6);
+
+        let inner = view.view(Bounds::new([0, 1], [1, 2])).unwrap();
+        assert_eq!(inner.to_map().to_string(), "gh\nkl\n");
+        assert!(view.view(Bounds::new([0, 0], [3, 0])).is_none());
+
+        // sparse maps are iterated instead of the possibly huge view extent
+        let mut sparse: Map<[i64; 2], char> = Map::new();
+        sparse.set([-1000, 0], 'a');
+        sparse.set([1000, 7], 'b');
+        let wide = sparse.view(Bounds::new([0, 0], [2000, 7]));
+        let tiles: Vec<([i64; 2], &char)> = wide.iter().collect();
+        assert_eq!(tiles, vec![([1000, 7], &'b')]);
+    }
+}